@@ -1,13 +1,14 @@
 extern crate clap;
 
-use std::path::PathBuf;
+use std::env;
 use clap::{
     Arg,
     App,
+    ArgMatches,
     SubCommand,
 };
 
-use crate::utils::is_repo;
+use crate::utils::parse_age;
 use crate::error::{
     Error,
     Result,
@@ -17,43 +18,224 @@ use crate::gf::{
     GfCmds,
     GfWork,
 };
+use crate::flow::Flow;
+use crate::output::Format;
+
+// top level commands a user defined branch type can't take the name of
+const RESERVED: &[&str] = &[
+    "init", "config", "status", "log", "undo", "archive", "backport",
+    "finish", "publish", "rebase", "diff", "delete", "help",
+];
+
+const EXIT_CODES: &str = "EXIT CODES:
+    0   success
+    1   generic failure
+    2   no HEAD, detached or unborn
+    3   git-flow is not initialized
+    4   branch already exists
+    5   branch not found
+    6   working tree has uncommitted changes
+    7   merge conflict
+    8   tag already exists
+    9   remote rejected the push
+    10  invalid branch or tag name
+    11  io error
+    12  other git error
+    13  branch is not merged
+    14  start base is not a branch, tag or commit
+    15  not in a git repository
+    16  bare repository without a working tree";
+
+// the strings the subcommands of a user defined branch type borrow
+struct CustomType {
+    name: String,
+    arg: String,
+    about: String,
+}
+
+// `-C <path>` has to be applied before anything looks at the repository,
+// the custom branch types included, so it is picked out of the options
+// ahead of the command. like git, every one is relative to the previous
+fn change_dir() -> Result<()> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let dir = match arg.as_str() {
+            "-C" => args.next(),
+            "--format" | "--remote" => {
+                args.next();
+                continue;
+            }
+            _ if arg.starts_with("-C") => Some(arg[2..].to_string()),
+            _ if arg.starts_with('-') => continue,
+            _ => break,
+        };
+        let dir = dir.ok_or_else(|| Error::Generic("-C needs a path".to_string()))?;
+        // git ignores an empty path
+        if !dir.is_empty() {
+            env::set_current_dir(&dir)
+                .map_err(|e| Error::Generic(format!("cannot change to {}: {}", dir, e)))?;
+        }
+    }
+
+    Ok(())
+}
 
 pub fn cli_run() -> Result<String> {
-    let matches = App::new("git-flow")
+    change_dir()?;
+
+    // user defined branch types get the same commands as the built-in ones
+    let custom_types = Flow::discover()
+        .and_then(|flow| flow.branch_types())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| matches!(t, GfBranch::Custom(name) if !RESERVED.contains(&name.as_str())))
+        .map(|t| CustomType {
+            name: t.name().to_string(),
+            arg: format!("{}_name", t.name()),
+            about: format!("Manage your {} branches.", t.name()),
+        })
+        .collect::<Vec<_>>();
+
+    let mut app = App::new("git-flow")
         .version("0.1.0")
         .author("Jason Wang <wang_borong@163.com>")
         .about("Workflow in git")
+        .after_help(EXIT_CODES)
+        .arg(Arg::with_name("directory")
+            .short("C")
+            .help("Run as if git-flow was started in <path>")
+            .value_name("path")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .help("Output format of the results")
+            .takes_value(true)
+            .possible_values(&["text", "json"])
+            .global(true))
+        .arg(Arg::with_name("porcelain")
+            .long("porcelain")
+            .help("Stable tab separated output for scripts")
+            .global(true))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Print what would be done without touching any ref or the working tree")
+            .global(true))
+        .arg(Arg::with_name("remote")
+            .long("remote")
+            .help("Remote to use instead of gitflow.origin and gitflow.upstream")
+            .takes_value(true)
+            .global(true))
         // Init subcommand
         .subcommand(SubCommand::with_name("init")
             .about("Setup a git repository for git flow usage.")
+            .arg(Arg::with_name("defaults")
+                .short("d")
+                .long("defaults")
+                .help("Take the default of every setting that isn't set instead of asking")
+                .conflicts_with("force"))
+            .arg(Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Ask for every setting again, also the ones already set"))
             .arg(Arg::with_name("init_path")
                 .help("Path to be initialized")))
         // Config subcommand
         .subcommand(SubCommand::with_name("config")
             .about("Show the git-flow configurations"))
+        // Status subcommand
+        .subcommand(SubCommand::with_name("status")
+            .about("Show the current branch, master and develop against their remotes and all open branches"))
+        // Log subcommand
+        .subcommand(SubCommand::with_name("log")
+            .about("Show the journal of the operations git-flow did"))
+        // Undo subcommand
+        .subcommand(SubCommand::with_name("undo")
+            .about("Revert the latest operation in the journal")
+            .arg(Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Undo even if the refs moved since the operation")))
+        // Archive subcommand
+        .subcommand(SubCommand::with_name("archive")
+            .about("Manage the tips kept of deleted branches")
+            .subcommand(SubCommand::with_name("list")
+                .about("Lists the archived branches"))
+            .subcommand(SubCommand::with_name("prune")
+                .about("Drop old archives")
+                .arg(Arg::with_name("older-than")
+                    .long("older-than")
+                    .help("Age of the archives to drop, e.g. 30d, 2w or 12h")
+                    .takes_value(true)
+                    .required(true))))
+        // Backport subcommand
+        .subcommand(SubCommand::with_name("backport")
+            .about("Cherry-pick a fix onto support branches")
+            .arg(Arg::with_name("continue")
+                .long("continue")
+                .help("Carry on after the conflicts are resolved and staged")
+                .conflicts_with_all(&["abort", "source"]))
+            .arg(Arg::with_name("abort")
+                .long("abort")
+                .help("Give up a backport stopped at a conflict")
+                .conflicts_with("source"))
+            .arg(Arg::with_name("source")
+                .help("The commit, or the tag of a finished hotfix, to backport")
+                .required_unless_one(&["continue", "abort"]))
+            .arg(Arg::with_name("support")
+                .help("The support lines to backport to, all of them when none is given")
+                .multiple(true)))
+        // Shortcuts working on the current branch
+        .subcommand(shortcut("finish", "Finish the current branch, or the one given")
+            .arg(fetch_arg())
+            .arg(message_arg())
+            .arg(no_edit_arg())
+            .arg(tag_arg())
+            .arg(no_backmerge_arg())
+            .arg(release_arg())
+            .arg(no_release_arg()))
+        .subcommand(shortcut("publish", "Publish the current branch, or the one given, on the remote"))
+        .subcommand(shortcut("rebase", "Rebase the current branch, or the one given, on its base")
+            .arg(Arg::with_name("interactive")
+                .short("i")
+                .help("Do an interactive rebase"))
+            .arg(Arg::with_name("rebase-merges")
+                .short("r")
+                .help("Preserve merges")))
+        .subcommand(shortcut("diff", "Show the changes of the current branch, or the one given, against its base"))
+        .subcommand(shortcut("delete", "Delete the current branch, or the one given")
+            .arg(force_arg())
+            .arg(force_worktree_arg()))
         // Feature subcommand
         .subcommand(SubCommand::with_name("feature")
             .about("Manage your feature branches.")
             .subcommand(SubCommand::with_name("start")
                 .about("Start new feature branch.")
+                .arg(fetch_arg())
+                .arg(base_option_arg())
+                .arg(worktree_arg())
+                .arg(base_arg())
                 .arg(Arg::with_name("feature_name")
                     .help("The new feature to be started")
                     .required(true)
                     .index(1)))
             .subcommand(SubCommand::with_name("finish")
                 .about("Finish feature branch")
+                .arg(fetch_arg())
+                .arg(message_arg())
+                .arg(no_edit_arg())
                 .arg(Arg::with_name("feature_name")
-                    .help("The feature to be finished")
-                    .required(true)
+                    .help("The feature to be finished, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("list")
                 .about("Lists all the existing feature branches in the local repository"))
             .subcommand(SubCommand::with_name("publish")
-                .about("Publish feature branch on origin.")
+                .about("Publish feature branch on the remote.")
                 .arg(Arg::with_name("feature_name")
-                    .help("The feature to be published")))
+                    .help("The feature to be published, the current branch when left out")))
             .subcommand(SubCommand::with_name("track")
-                .about("Start tracking feature that is shared on origin")
+                .about("Start tracking feature that is shared on the remote")
                 .arg(Arg::with_name("feature_name")
                     .help("The feature branch to be tracked")
                     .required(true)
@@ -61,7 +243,7 @@ pub fn cli_run() -> Result<String> {
             .subcommand(SubCommand::with_name("diff")
                 .about("Show all changes in feature branch that are not in the base branch.")
                 .arg(Arg::with_name("feature_name")
-                    .help("The feature to be checked")))
+                    .help("The feature to be checked, the current branch when left out")))
             .subcommand(SubCommand::with_name("rebase")
                 .about("Rebase feature on develop")
                 .arg(Arg::with_name("interactive")
@@ -71,7 +253,7 @@ pub fn cli_run() -> Result<String> {
                     .short("r")
                     .help("Preserve merges"))
                 .arg(Arg::with_name("feature_name")
-                    .help("The feature branch to be rebased")
+                    .help("The feature branch to be rebased, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("checkout")
                 .about("Switch to feature branch")
@@ -81,8 +263,22 @@ pub fn cli_run() -> Result<String> {
                     .index(1)))
             .subcommand(SubCommand::with_name("delete")
                 .about("Delete a given feature branch")
+                .arg(force_arg())
+                .arg(force_worktree_arg())
                 .arg(Arg::with_name("feature_name")
-                    .help("The feature branch to be deleted")
+                    .help("The feature branch to be deleted, the current branch when left out")
+                    .index(1)))
+            .subcommand(SubCommand::with_name("rename")
+                .about("Rename a given feature branch")
+                .arg(Arg::with_name("feature_name")
+                    .help("The feature branch to be renamed")
+                    .required(true)
+                    .index(1))
+                .arg(new_name_arg()))
+            .subcommand(SubCommand::with_name("restore")
+                .about("Recreate a deleted feature branch from its archive")
+                .arg(Arg::with_name("feature_name")
+                    .help("The feature branch to be restored")
                     .required(true)
                     .index(1)))
         )
@@ -91,32 +287,54 @@ pub fn cli_run() -> Result<String> {
             .about("git flow release")
             .subcommand(SubCommand::with_name("start")
                 .about("release start command")
+                .arg(fetch_arg())
+                .arg(base_option_arg())
+                .arg(worktree_arg())
+                .arg(base_arg())
                 .arg(Arg::with_name("release_name")
                     .help("work on a release branch")
                     .required(true)
                     .index(1)))
             .subcommand(SubCommand::with_name("finish")
                 .about("release finish command")
+                .arg(fetch_arg())
+                .arg(message_arg())
+                .arg(no_edit_arg())
+                .arg(tag_arg())
+                .arg(no_backmerge_arg())
                 .arg(Arg::with_name("release_name")
-                    .help("work off a release branch")
-                    .required(true)
+                    .help("work off a release branch, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("list")
                 .about("release list command"))
             .subcommand(SubCommand::with_name("publish")
-                .about("Publish release branch on origin.")
+                .about("Publish release branch on the remote.")
                 .arg(Arg::with_name("release_name")
-                    .help("The release to be published")))
+                    .help("The release to be published, the current branch when left out")))
             .subcommand(SubCommand::with_name("track")
-                .about("Start tracking release that is shared on origin")
+                .about("Start tracking release that is shared on the remote")
                 .arg(Arg::with_name("release_name")
                     .help("The release branch to be tracked")
                     .required(true)
                     .index(1)))
             .subcommand(SubCommand::with_name("delete")
                 .about("Delete a given release branch")
+                .arg(force_arg())
+                .arg(force_worktree_arg())
+                .arg(Arg::with_name("release_name")
+                    .help("The release branch to be deleted, the current branch when left out")
+                    .index(1)))
+            .subcommand(SubCommand::with_name("rename")
+                .about("Rename a given release branch")
+                .arg(Arg::with_name("release_name")
+                    .help("The release branch to be renamed")
+                    .required(true)
+                    .index(1))
+                .arg(new_name_arg()))
+            .subcommand(SubCommand::with_name("restore")
+                .about("Recreate a deleted release branch from its archive")
                 .arg(Arg::with_name("release_name")
-                    .help("The release branch to be deleted")
+                    .help("The release branch to be restored")
                     .required(true)
                     .index(1)))
         )
@@ -125,26 +343,50 @@ pub fn cli_run() -> Result<String> {
             .about("git flow hotfix")
             .subcommand(SubCommand::with_name("start")
                 .about("hotfix start command")
+                .arg(fetch_arg())
+                .arg(base_option_arg())
+                .arg(worktree_arg())
+                .arg(base_arg())
                 .arg(Arg::with_name("hotfix_name")
                     .help("work on a hotfix branch")
                     .required(true)
                     .index(1)))
             .subcommand(SubCommand::with_name("finish")
                 .about("hotfix finish command")
+                .arg(fetch_arg())
+                .arg(message_arg())
+                .arg(no_edit_arg())
+                .arg(tag_arg())
+                .arg(no_backmerge_arg())
+                .arg(release_arg())
+                .arg(no_release_arg())
                 .arg(Arg::with_name("hotfix_name")
-                    .help("work off a hotfix branch")
-                    .required(true)
+                    .help("work off a hotfix branch, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("list")
                 .about("hotfix list command"))
             .subcommand(SubCommand::with_name("publish")
-                .about("Publish feature branch on origin.")
-                .arg(Arg::with_name("feature_name")
-                    .help("The feature to be published")))
+                .about("Publish hotfix branch on the remote.")
+                .arg(Arg::with_name("hotfix_name")
+                    .help("The hotfix to be published, the current branch when left out")))
             .subcommand(SubCommand::with_name("delete")
-                .about("Delete a given feature branch")
-                .arg(Arg::with_name("feature_name")
-                    .help("The feature branch to be deleted")
+                .about("Delete a given hotfix branch")
+                .arg(force_arg())
+                .arg(force_worktree_arg())
+                .arg(Arg::with_name("hotfix_name")
+                    .help("The hotfix branch to be deleted, the current branch when left out")
+                    .index(1)))
+            .subcommand(SubCommand::with_name("rename")
+                .about("Rename a given hotfix branch")
+                .arg(Arg::with_name("hotfix_name")
+                    .help("The hotfix branch to be renamed")
+                    .required(true)
+                    .index(1))
+                .arg(new_name_arg()))
+            .subcommand(SubCommand::with_name("restore")
+                .about("Recreate a deleted hotfix branch from its archive")
+                .arg(Arg::with_name("hotfix_name")
+                    .help("The hotfix branch to be restored")
                     .required(true)
                     .index(1)))
         )
@@ -152,24 +394,30 @@ pub fn cli_run() -> Result<String> {
             .about("git flow bugfix")
             .subcommand(SubCommand::with_name("start")
                 .about("bugfix start command")
+                .arg(fetch_arg())
+                .arg(base_option_arg())
+                .arg(worktree_arg())
+                .arg(base_arg())
                 .arg(Arg::with_name("bugfix_name")
                     .help("work on a bugfix branch")
                     .required(true)
                     .index(1)))
             .subcommand(SubCommand::with_name("finish")
                 .about("bugfix finish command")
+                .arg(fetch_arg())
+                .arg(message_arg())
+                .arg(no_edit_arg())
                 .arg(Arg::with_name("bugfix_name")
-                    .help("work off a bugfix branch")
-                    .required(true)
+                    .help("work off a bugfix branch, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("list")
                 .about("bugfix list command"))
             .subcommand(SubCommand::with_name("publish")
-                .about("Publish bugfix branch on origin.")
+                .about("Publish bugfix branch on the remote.")
                 .arg(Arg::with_name("bugfix_name")
-                    .help("The bugfix to be published")))
+                    .help("The bugfix to be published, the current branch when left out")))
             .subcommand(SubCommand::with_name("track")
-                .about("Start tracking bugfix that is shared on origin")
+                .about("Start tracking bugfix that is shared on the remote")
                 .arg(Arg::with_name("bugfix_name")
                     .help("The bugfix branch to be tracked")
                     .required(true)
@@ -177,7 +425,7 @@ pub fn cli_run() -> Result<String> {
             .subcommand(SubCommand::with_name("diff")
                 .about("Show all changes in bugfix branch that are not in the base branch.")
                 .arg(Arg::with_name("bugfix_name")
-                    .help("The bugfix to be checked")))
+                    .help("The bugfix to be checked, the current branch when left out")))
             .subcommand(SubCommand::with_name("rebase")
                 .about("Rebase bugfix on develop")
                 .arg(Arg::with_name("interactive")
@@ -187,7 +435,7 @@ pub fn cli_run() -> Result<String> {
                     .short("r")
                     .help("Preserve merges"))
                 .arg(Arg::with_name("bugfix_name")
-                    .help("The bugfix branch to be rebased")
+                    .help("The bugfix branch to be rebased, the current branch when left out")
                     .index(1)))
             .subcommand(SubCommand::with_name("checkout")
                 .about("Switch to bugfix branch")
@@ -197,8 +445,22 @@ pub fn cli_run() -> Result<String> {
                     .index(1)))
             .subcommand(SubCommand::with_name("delete")
                 .about("Delete a given bugfix branch")
+                .arg(force_arg())
+                .arg(force_worktree_arg())
+                .arg(Arg::with_name("bugfix_name")
+                    .help("The bugfix branch to be deleted, the current branch when left out")
+                    .index(1)))
+            .subcommand(SubCommand::with_name("rename")
+                .about("Rename a given bugfix branch")
+                .arg(Arg::with_name("bugfix_name")
+                    .help("The bugfix branch to be renamed")
+                    .required(true)
+                    .index(1))
+                .arg(new_name_arg()))
+            .subcommand(SubCommand::with_name("restore")
+                .about("Recreate a deleted bugfix branch from its archive")
                 .arg(Arg::with_name("bugfix_name")
-                    .help("The bugfix branch to be deleted")
+                    .help("The bugfix branch to be restored")
                     .required(true)
                     .index(1)))
         )
@@ -206,140 +468,99 @@ pub fn cli_run() -> Result<String> {
             .about("git flow support")
             .subcommand(SubCommand::with_name("start")
                 .about("support start command")
+                .arg(fetch_arg())
                 .arg(Arg::with_name("support_name")
-                    .help("work on a bugfix branch")
+                    .help("The new support line, e.g. 1.x")
                     .required(true)
                     .index(1))
-                .arg(Arg::with_name("base_branch")
-                    .help("the based branch which a support starts from")
+                .arg(Arg::with_name("base")
+                    .help("The branch, tag or commit the support starts from")
                     .required(true)
                     .index(2)))
             .subcommand(SubCommand::with_name("list")
-                .about("bugfix list command"))
-        )
+                .about("support list command"))
+        );
         // ...
-        .get_matches();
+    for t in &custom_types {
+        app = app.subcommand(custom_subcommand(t));
+    }
+    let matches = app.get_matches();
+
+    let format = output_format(&matches)?;
 
     // Init
     if let Some(matches) = matches.subcommand_matches("init") {
-        let path = matches.value_of("init_path").unwrap_or(".");
-        let mut gfwork = GfWork::new(&PathBuf::from(path));
+        // only init may create a repository, in the one git would use
+        // when there is no path
+        let flow = match matches.value_of("init_path") {
+            Some(path) => Flow::create(path)?,
+            None => match Flow::discover() {
+                Err(Error::NotRepository(_)) => Flow::create(".")?,
+                flow => flow?,
+            },
+        };
+        let mut gfwork = GfWork::new(flow);
 
         gfwork.set_subcmd(GfCmds::Init);
-        gfwork.work()?;
-
-        return Ok(format!("init {} success", path));
-    }
+        gfwork.set_format(format);
+        gfwork.set_force(matches.is_present("force"));
+        gfwork.set_defaults(matches.is_present("defaults"));
+        gfwork.set_dry_run(innermost(matches).is_present("dry-run"));
 
-    if !is_repo(".") {
-        return Err(Error::Generic(
-                "This is not a repo, use git-flow init ot initialize it".to_string()));
+        return gfwork.work()?.render(format);
     }
 
-    let mut gfwork = GfWork::new(&PathBuf::from("."));
+    let mut gfwork = GfWork::new(Flow::discover()?);
+    gfwork.set_format(format);
+    gfwork.set_remote(innermost(&matches).value_of("remote"));
+    gfwork.set_dry_run(innermost(&matches).is_present("dry-run"));
 
     match matches.subcommand() {
-        ("feature", feature_matches) => {
-            // set command
-            gfwork.set_cmd(GfBranch::Feature);
-            // set subcommand
-            match feature_matches.unwrap().subcommand() {
-                ("start", feature_start_matches) => {
-                    gfwork.set_subcmd(GfCmds::Start);
-                    // set branch suffix
-                    gfwork.set_branch_suffix(
-                        &feature_start_matches.unwrap()
-                        .value_of("feature_name").unwrap());
-                }
-                ("finish", feature_finish_matches) => {
-                    gfwork.set_subcmd(GfCmds::Finish);
-                    gfwork.set_branch_suffix(
-                        &feature_finish_matches.unwrap()
-                        .value_of("feature_name").unwrap());
-                }
-                // ...
-                _ => {}
-            }
-
+        ("config", _) => {
+            gfwork.set_subcmd(GfCmds::Config);
         }
-        ("release", release_matches) => {
-            gfwork.set_cmd(GfBranch::Release);
-            match release_matches.unwrap().subcommand() {
-                ("start", release_start_matches) => {
-                    gfwork.set_subcmd(GfCmds::Start);
-                    // set branch suffix
-                    gfwork.set_branch_suffix(
-                        &release_start_matches.unwrap()
-                        .value_of("release_name").unwrap());
-                }
-                ("finish", release_finish_matches) => {
-                    gfwork.set_subcmd(GfCmds::Finish);
-                    gfwork.set_branch_suffix(
-                        &release_finish_matches.unwrap()
-                        .value_of("release_name").unwrap());
-                }
-                // ...
-                _ => {}
-            }
+        ("status", _) => {
+            gfwork.set_subcmd(GfCmds::Status);
         }
-        ("bugfix", bugfix_matches) => {
-            gfwork.set_cmd(GfBranch::Bugfix);
-            match bugfix_matches.unwrap().subcommand() {
-                ("start", bugfix_start_matches) => {
-                    gfwork.set_subcmd(GfCmds::Start);
-                    // set branch suffix
-                    gfwork.set_branch_suffix(
-                        &bugfix_start_matches.unwrap()
-                        .value_of("bugfix_name").unwrap());
-                }
-                ("finish", bugfix_finish_matches) => {
-                    gfwork.set_subcmd(GfCmds::Finish);
-                    gfwork.set_branch_suffix(
-                        &bugfix_finish_matches.unwrap()
-                        .value_of("bugfix_name").unwrap());
+        ("log", _) => {
+            gfwork.set_subcmd(GfCmds::Log);
+        }
+        ("archive", Some(archive_matches)) => {
+            match archive_matches.subcommand() {
+                ("prune", Some(m)) => {
+                    gfwork.set_subcmd(GfCmds::ArchivePrune);
+                    gfwork.set_older_than(m.value_of("older-than").map(parse_age).transpose()?);
                 }
-                // ...
-                _ => {}
+                _ => gfwork.set_subcmd(GfCmds::ArchiveList),
             }
         }
-        ("hotfix", hotfix_matches) => {
-            gfwork.set_cmd(GfBranch::Hotfix);
-            match hotfix_matches.unwrap().subcommand() {
-                ("start", hotfix_start_matches) => {
-                    gfwork.set_subcmd(GfCmds::Start);
-                    // set branch suffix
-                    gfwork.set_branch_suffix(
-                        &hotfix_start_matches.unwrap()
-                        .value_of("hotfix_name").unwrap());
-                }
-                ("finish", hotfix_finish_matches) => {
-                    gfwork.set_subcmd(GfCmds::Finish);
-                    gfwork.set_branch_suffix(
-                        &hotfix_finish_matches.unwrap()
-                        .value_of("hotfix_name").unwrap());
-                }
-                // ...
-                _ => {}
+        ("backport", Some(m)) => {
+            if m.is_present("continue") {
+                gfwork.set_subcmd(GfCmds::BackportContinue);
+            } else if m.is_present("abort") {
+                gfwork.set_subcmd(GfCmds::BackportAbort);
+            } else {
+                gfwork.set_subcmd(GfCmds::Backport);
+                gfwork.set_source(m.value_of("source"));
+                gfwork.set_lines(m.values_of("support")
+                    .map(|v| v.map(|l| l.to_string()).collect())
+                    .unwrap_or_default());
             }
         }
-        ("support", support_matches) => {
-            gfwork.set_cmd(GfBranch::Support);
-            match support_matches.unwrap().subcommand() {
-                ("start", support_start_matches) => {
-                    gfwork.set_subcmd(GfCmds::Start);
-                    // set branch suffix
-                    gfwork.set_branch_suffix(
-                        &support_start_matches.unwrap()
-                        .value_of("support_name").unwrap());
-                }
-                ("finish", support_finish_matches) => {
-                    gfwork.set_subcmd(GfCmds::Finish);
-                    gfwork.set_branch_suffix(
-                        &support_finish_matches.unwrap()
-                        .value_of("support_name").unwrap());
-                }
-                // ...
-                _ => {}
+        ("undo", undo_matches) => {
+            gfwork.set_subcmd(GfCmds::Undo);
+            gfwork.set_force(undo_matches.is_some_and(|m| m.is_present("force")));
+        }
+        ("finish", m) => set_sub_work(&mut gfwork, GfCmds::Finish, m, "branch")?,
+        ("publish", m) => set_sub_work(&mut gfwork, GfCmds::Publish, m, "branch")?,
+        ("rebase", m) => set_sub_work(&mut gfwork, GfCmds::Rebase, m, "branch")?,
+        ("diff", m) => set_sub_work(&mut gfwork, GfCmds::Diff, m, "branch")?,
+        ("delete", m) => set_sub_work(&mut gfwork, GfCmds::Delete, m, "branch")?,
+        (name, Some(branch_matches)) => {
+            if let Some(cmd) = GfBranch::from_name(name) {
+                set_branch_work(&mut gfwork, cmd, branch_matches)?;
+            } else if custom_types.iter().any(|t| t.name == name) {
+                set_branch_work(&mut gfwork, GfBranch::Custom(name.to_string()), branch_matches)?;
             }
         }
         // ...
@@ -347,8 +568,238 @@ pub fn cli_run() -> Result<String> {
         }
     }
 
-    gfwork.work()?;
+    gfwork.work()?.render(format)
+}
+
+fn custom_subcommand(t: &CustomType) -> App<'_, '_> {
+    let name_arg = |help| Arg::with_name(&t.arg)
+        .help(help)
+        .required(true)
+        .index(1);
+    let current_arg = |help| Arg::with_name(&t.arg)
+        .help(help)
+        .index(1);
+
+    SubCommand::with_name(&t.name)
+        .about(t.about.as_str())
+        .subcommand(SubCommand::with_name("start")
+            .about("Start a new branch")
+            .arg(fetch_arg())
+            .arg(base_option_arg())
+            .arg(worktree_arg())
+            .arg(base_arg())
+            .arg(name_arg("The branch to be started")))
+        .subcommand(SubCommand::with_name("finish")
+            .about("Finish a branch")
+            .arg(fetch_arg())
+            .arg(message_arg())
+            .arg(no_edit_arg())
+            .arg(tag_arg())
+            .arg(current_arg("The branch to be finished, the current branch when left out")))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists all the existing branches of this type in the local repository"))
+        .subcommand(SubCommand::with_name("publish")
+            .about("Publish a branch on the remote.")
+            .arg(current_arg("The branch to be published, the current branch when left out")))
+        .subcommand(SubCommand::with_name("track")
+            .about("Start tracking a branch that is shared on the remote")
+            .arg(name_arg("The branch to be tracked")))
+        .subcommand(SubCommand::with_name("checkout")
+            .about("Switch to a branch")
+            .arg(name_arg("The branch to be checked out")))
+        .subcommand(SubCommand::with_name("delete")
+            .about("Delete a given branch")
+            .arg(force_arg())
+            .arg(force_worktree_arg())
+            .arg(current_arg("The branch to be deleted, the current branch when left out")))
+        .subcommand(SubCommand::with_name("rename")
+            .about("Rename a given branch")
+            .arg(name_arg("The branch to be renamed"))
+            .arg(new_name_arg()))
+        .subcommand(SubCommand::with_name("restore")
+            .about("Recreate a deleted branch from its archive")
+            .arg(name_arg("The branch to be restored")))
+}
+
+fn base_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("base")
+        .help("The branch, tag or commit to start from instead of the configured base")
+        .index(2)
+}
+
+fn new_name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("new_name")
+        .help("The new name, checked against the naming policy of the type")
+        .required(true)
+        .index(2)
+}
+
+fn message_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("message")
+        .short("m")
+        .long("message")
+        .help("Message of the merge, {branch}, {type}, {name}, {base} and {version} are filled in")
+        .takes_value(true)
+}
+
+fn no_edit_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("no-edit")
+        .long("no-edit")
+        .help("Use the configured merge message without opening an editor")
+}
+
+fn tag_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tag")
+        .short("T")
+        .long("tag")
+        .help("Name of the tag, the version tag prefix followed by the branch name by default")
+        .takes_value(true)
+        .value_name("name")
+}
 
-    Ok(format!("run ... success"))
+// where a hotfix goes instead of develop
+fn release_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("release")
+        .long("release")
+        .help("Merge a hotfix into this open release instead of develop")
+        .takes_value(true)
+        .value_name("release_name")
+        .conflicts_with("no-release")
+}
+
+fn no_release_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("no-release")
+        .long("no-release")
+        .help("Merge a hotfix into develop even if a release is open")
+}
+
+fn no_backmerge_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("nobackmerge")
+        .long("nobackmerge")
+        .help("Don't merge the finished branch back into develop")
+}
+
+// `--base` for those who rather name it
+fn base_option_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("base_option")
+        .long("base")
+        .help("The branch, tag or commit to start from, e.g. support/1.x for a hotfix of a support line")
+        .takes_value(true)
+        .value_name("base")
+        .conflicts_with("base")
+}
+
+// the path needs `=`, a separate value would take the branch name
+fn worktree_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("worktree")
+        .long("worktree")
+        .help("Check the new branch out in a linked worktree, ../<repo>-<name> unless --worktree=<path> is given")
+        .takes_value(true)
+        .value_name("path")
+        .min_values(0)
+        .require_equals(true)
+}
+
+fn force_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force")
+        .short("f")
+        .long("force")
+        .help("Delete the branch even if it is not merged")
+}
+
+fn force_worktree_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force-worktree")
+        .long("force-worktree")
+        .help("Remove the worktree of the branch even if it has uncommitted or untracked files")
+}
+
+fn fetch_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("fetch")
+        .short("F")
+        .long("fetch")
+        .help("Fetch the base branches from the upstream remote first")
+}
+
+// global args end up on the innermost subcommand
+fn innermost<'a>(matches: &'a ArgMatches<'a>) -> &'a ArgMatches<'a> {
+    let mut m = matches;
+    while let (_, Some(sub)) = m.subcommand() {
+        m = sub;
+    }
+
+    m
+}
+
+fn output_format(matches: &ArgMatches) -> Result<Format> {
+    let m = innermost(matches);
+
+    if m.is_present("porcelain") {
+        Ok(Format::Porcelain)
+    } else {
+        m.value_of("format").unwrap_or("text").parse()
+    }
+}
+
+// `git flow <command>` working on the current branch, or the full branch
+// name given
+fn shortcut<'a, 'b>(name: &'a str, about: &'a str) -> App<'a, 'b> {
+    SubCommand::with_name(name)
+        .about(about)
+        .arg(Arg::with_name("branch")
+            .help("The full branch name, e.g. feature/x, the current branch when left out")
+            .index(1))
+}
+
+// set the subcommand of a branch type command and its branch suffix
+fn set_branch_work(gfwork: &mut GfWork, cmd: GfBranch, matches: &ArgMatches) -> Result<()> {
+    let name_arg = format!("{}_name", cmd.name());
+
+    // set command
+    gfwork.set_cmd(cmd);
+    // set subcommand
+    let (subcmd, sub_matches) = match matches.subcommand() {
+        ("start", sub_matches) => (GfCmds::Start, sub_matches),
+        ("finish", sub_matches) => (GfCmds::Finish, sub_matches),
+        ("list", sub_matches) => (GfCmds::List, sub_matches),
+        ("publish", sub_matches) => (GfCmds::Publish, sub_matches),
+        ("track", sub_matches) => (GfCmds::Track, sub_matches),
+        ("checkout", sub_matches) => (GfCmds::Checkout, sub_matches),
+        ("delete", sub_matches) => (GfCmds::Delete, sub_matches),
+        ("rename", sub_matches) => (GfCmds::Rename, sub_matches),
+        ("restore", sub_matches) => (GfCmds::Restore, sub_matches),
+        ("rebase", sub_matches) => (GfCmds::Rebase, sub_matches),
+        ("diff", sub_matches) => (GfCmds::Diff, sub_matches),
+        // ...
+        _ => return Ok(()),
+    };
+
+    set_sub_work(gfwork, subcmd, sub_matches, &name_arg)
+}
+
+// set a subcommand with its flags, the branch is taken from `name_arg`
+fn set_sub_work(gfwork: &mut GfWork, subcmd: GfCmds, sub_matches: Option<&ArgMatches>, name_arg: &str) -> Result<()> {
+    if sub_matches.is_some_and(|m| m.is_present("interactive") || m.is_present("rebase-merges")) {
+        return Err(Error::Generic("interactive rebases and rebasing merges are not supported, use git rebase".to_string()));
+    }
+
+    gfwork.set_subcmd(subcmd);
+    gfwork.set_fetch(sub_matches.is_some_and(|m| m.is_present("fetch")));
+    gfwork.set_force(sub_matches.is_some_and(|m| m.is_present("force")));
+    gfwork.set_force_worktree(sub_matches.is_some_and(|m| m.is_present("force-worktree")));
+    gfwork.set_base(sub_matches.and_then(|m| m.value_of("base").or_else(|| m.value_of("base_option"))));
+    gfwork.set_worktree(sub_matches.is_some_and(|m| m.is_present("worktree")));
+    gfwork.set_worktree_path(sub_matches.and_then(|m| m.value_of("worktree")));
+    gfwork.set_new_name(sub_matches.and_then(|m| m.value_of("new_name")));
+    gfwork.set_message(sub_matches.and_then(|m| m.value_of("message")));
+    gfwork.set_no_edit(sub_matches.is_some_and(|m| m.is_present("no-edit")));
+    gfwork.set_tag(sub_matches.and_then(|m| m.value_of("tag")));
+    gfwork.set_release(sub_matches.and_then(|m| m.value_of("release")));
+    gfwork.set_no_release(sub_matches.is_some_and(|m| m.is_present("no-release")));
+    gfwork.set_no_backmerge(sub_matches.is_some_and(|m| m.is_present("nobackmerge")));
+    // set branch suffix
+    if let Some(suffix) = sub_matches.and_then(|m| m.value_of(name_arg)) {
+        gfwork.set_branch_suffix(suffix);
+    }
 
+    Ok(())
 }
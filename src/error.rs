@@ -2,6 +2,28 @@ extern crate thiserror;
 
 use thiserror::Error;
 
+/// Every error git-flow reports, each one with its own exit code.
+///
+/// The exit codes are stable, scripts may rely on them:
+///
+/// | code | error |
+/// |------|-------|
+/// | 1    | `Generic` |
+/// | 2    | `NoHead` |
+/// | 3    | `NotInitialized` |
+/// | 4    | `BranchExists` |
+/// | 5    | `BranchNotFound` |
+/// | 6    | `DirtyWorkingTree` |
+/// | 7    | `MergeConflict` |
+/// | 8    | `TagExists` |
+/// | 9    | `RemoteRejected` |
+/// | 10   | `InvalidName` |
+/// | 11   | `Io` |
+/// | 12   | `Git` |
+/// | 13   | `NotMerged` |
+/// | 14   | `InvalidBase` |
+/// | 15   | `NotRepository` |
+/// | 16   | `NoWorkTree` |
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("`{0}`")]
@@ -10,6 +32,55 @@ pub enum Error {
     #[error("git: no head found")]
     NoHead,
 
+    #[error("git-flow is not initialized, missing `{0}`")]
+    NotInitialized(String),
+
+    #[error("branch `{0}` already exists")]
+    BranchExists(String),
+
+    #[error("branch `{0}` not found")]
+    BranchNotFound(String),
+
+    #[error("the working tree contains uncommitted changes")]
+    DirtyWorkingTree,
+
+    #[error("merging `{from}` into `{into}` caused conflicts")]
+    MergeConflict {
+        from: String,
+        into: String,
+    },
+
+    #[error("tag `{0}` already exists")]
+    TagExists(String),
+
+    #[error("remote `{remote}` rejected `{refname}`: {reason}")]
+    RemoteRejected {
+        remote: String,
+        refname: String,
+        reason: String,
+    },
+
+    #[error("invalid name `{name}`: {reason}")]
+    InvalidName {
+        name: String,
+        reason: String,
+    },
+
+    #[error("`{0}` is not a branch, tag or commit")]
+    InvalidBase(String),
+
+    #[error("branch `{branch}` is not merged into `{into}`")]
+    NotMerged {
+        branch: String,
+        into: String,
+    },
+
+    #[error("`{0}` is not in a git repository")]
+    NotRepository(String),
+
+    #[error("`{0}` is a bare repository without a working tree")]
+    NoWorkTree(String),
+
     #[error("io error:{0}")]
     Io(#[from] std::io::Error),
 
@@ -17,4 +88,60 @@ pub enum Error {
     Git(#[from] git2::Error),
 }
 
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Generic(_) => 1,
+            Error::NoHead => 2,
+            Error::NotInitialized(_) => 3,
+            Error::BranchExists(_) => 4,
+            Error::BranchNotFound(_) => 5,
+            Error::DirtyWorkingTree => 6,
+            Error::MergeConflict { .. } => 7,
+            Error::TagExists(_) => 8,
+            Error::RemoteRejected { .. } => 9,
+            Error::InvalidName { .. } => 10,
+            Error::Io(_) => 11,
+            Error::Git(_) => 12,
+            Error::NotMerged { .. } => 13,
+            Error::InvalidBase(_) => 14,
+            Error::NotRepository(_) => 15,
+            Error::NoWorkTree(_) => 16,
+        }
+    }
+
+    // what the user can do about it
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::NoHead => Some(
+                "check out a branch first, git-flow doesn't work on a detached or unborn HEAD".to_string()),
+            Error::NotInitialized(_) => Some(
+                "run `git flow init -d` to take the defaults, or `git flow init` to pick them".to_string()),
+            Error::BranchExists(b) => Some(format!(
+                "pick another name, or check out the existing branch with `git checkout {}`", b)),
+            Error::BranchNotFound(_) => Some(
+                "list the existing branches with `git flow <type> list`".to_string()),
+            Error::DirtyWorkingTree => Some(
+                "commit or stash your changes, then run the command again".to_string()),
+            Error::MergeConflict { into, .. } => Some(format!(
+                "nothing was changed, merge `{}` into your branch and resolve the conflicts there, then run the command again", into)),
+            Error::TagExists(_) => Some(
+                "give another tag name, or delete the existing tag with `git tag -d`".to_string()),
+            Error::RemoteRejected { refname, .. } => Some(format!(
+                "fetch and integrate the remote changes to `{}` before pushing again", refname)),
+            Error::InvalidName { .. } => Some(
+                "names must follow `git check-ref-format` and the naming policy in `gitflow.<type>.policy`".to_string()),
+            Error::InvalidBase(_) => Some(
+                "start from an existing branch, tag or commit, e.g. `develop` or `v1.0`".to_string()),
+            Error::NotMerged { .. } => Some(
+                "finish it first, or force the deletion with `-f`".to_string()),
+            Error::NotRepository(_) => Some(
+                "run `git flow init` to create one, or point to one with `-C <path>` or `GIT_DIR`".to_string()),
+            Error::NoWorkTree(_) => Some(
+                "run the command in a clone or a worktree, see `git worktree add`".to_string()),
+            _ => None,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
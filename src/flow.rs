@@ -0,0 +1,3714 @@
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{
+        Path,
+        PathBuf,
+    },
+    collections::BTreeMap,
+};
+
+use git2::{
+    Oid,
+    Commit,
+    BranchType,
+    ErrorCode,
+    DiffFormat,
+    ObjectType,
+    RepositoryState,
+    ResetType,
+    Sort,
+    build::CheckoutBuilder,
+};
+use serde::{
+    Serialize,
+    Serializer,
+    Deserialize,
+};
+use regex::Regex;
+
+use crate::{
+    gf::GfBranch,
+    gitc::{
+        GitcRepo,
+        MergeKind,
+        Worktree,
+    },
+    journal::{
+        Journal,
+        Entry,
+        RefState,
+    },
+    utils::{
+        get_branch_name,
+        check_ref_format,
+        now,
+    },
+    error::{
+        Error,
+        Result,
+    },
+};
+
+/// Settings written by [`Flow::init`].
+#[derive(Clone, Debug)]
+pub struct InitOptions {
+    pub master: String,
+    pub develop: String,
+    pub feature: String,
+    pub bugfix: String,
+    pub release: String,
+    pub hotfix: String,
+    pub support: String,
+    pub versiontag: String,
+    /// Hooks and filters directory, `core.hooksPath` or the `hooks`
+    /// directory of the common git directory when `None`.
+    pub hooks: Option<String>,
+    /// The remote flow branches are published to.
+    pub origin: String,
+    /// The remote master and develop are fetched from, `origin` when `None`.
+    pub upstream: Option<String>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            master: "master".to_string(),
+            develop: "develop".to_string(),
+            feature: "feature/".to_string(),
+            bugfix: "bugfix/".to_string(),
+            release: "release/".to_string(),
+            hotfix: "hotfix/".to_string(),
+            support: "support/".to_string(),
+            versiontag: String::new(),
+            hooks: None,
+            origin: "origin".to_string(),
+            upstream: None,
+        }
+    }
+}
+
+/// Options of [`Flow::start`].
+#[derive(Clone, Debug, Default)]
+pub struct StartOptions {
+    /// Create the branch without switching to it.
+    pub no_checkout: bool,
+    /// Fetch the base branch from the upstream remote first, and refuse
+    /// to start when the local one is behind.
+    pub fetch: bool,
+    /// Branch, tag or commit to start from instead of the configured base.
+    /// A branch is remembered in `branch.<name>.gitflowBase`, hotfixes
+    /// started from a support branch finish there, features and bugfixes
+    /// go back to it as long as it exists, e.g. a release branch.
+    pub base: Option<String>,
+    /// Check the branch out in a new linked worktree instead of switching
+    /// to it here.
+    pub worktree: bool,
+    /// Where the worktree goes, [`Flow::default_worktree`] when `None`.
+    pub worktree_path: Option<PathBuf>,
+}
+
+/// Options of [`Flow::finish`].
+#[derive(Clone, Debug, Default)]
+pub struct FinishOptions {
+    /// Message of the merge commit, the configured template or
+    /// `Merge branch '{branch}' into {base}` when `None`. `{branch}`,
+    /// `{type}`, `{name}`, `{base}` and `{version}` are filled in.
+    pub message: Option<String>,
+    /// Tag of a finished release or hotfix, the version tag prefix
+    /// followed by the branch name when `None`.
+    pub tag: Option<String>,
+    /// Message of the tag, `gitflow.<type>.finish.message` like git-flow
+    /// AVH or the tag name when `None`, with the placeholders of `message`.
+    pub tag_message: Option<String>,
+    /// Don't tag a finished release or hotfix.
+    pub no_tag: bool,
+    /// Keep the branch instead of deleting it.
+    pub keep: bool,
+    /// Fetch the branches merged into from the upstream remote first, and
+    /// refuse to finish when the local ones are behind.
+    pub fetch: bool,
+    /// The release a finished hotfix goes into instead of develop, picked
+    /// on its own when only one release branch is open.
+    pub release: Option<String>,
+    /// Merge a finished hotfix into develop even if a release is open.
+    pub no_release: bool,
+    /// Don't merge a finished release or hotfix back into develop.
+    pub no_backmerge: bool,
+}
+
+/// Options of [`Flow::delete`].
+#[derive(Clone, Debug, Default)]
+pub struct DeleteOptions {
+    /// Delete the branch even if it is not merged.
+    pub force: bool,
+    /// Remove the linked worktree of the branch even if it has uncommitted
+    /// or untracked files.
+    pub force_worktree: bool,
+}
+
+// `gitflow.<type>.policy = semver` stands for this
+const SEMVER: &str = r"^(0|[1-9]\d*)\.(0|[1-9]\d*)\.(0|[1-9]\d*)(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?$";
+
+// where deleted branches are kept, by `gitflow.archive` style
+const ARCHIVE_REFS: &str = "refs/gitflow/archive/";
+const ARCHIVE_TAGS: &str = "refs/tags/archive/";
+
+// what init writes that git-flow can't do without, the others have
+// defaults. git-flow AVH and nvie set them too
+const REQUIRED: &[&str] = &[
+    "gitflow.branch.master",
+    "gitflow.branch.develop",
+    "gitflow.prefix.feature",
+    "gitflow.prefix.release",
+    "gitflow.prefix.hotfix",
+    "gitflow.prefix.support",
+    "gitflow.prefix.versiontag",
+];
+
+// the settings read under `gitflow.`, the ones of git-flow AVH and nvie
+// included, anything else is reported as unsupported
+const SUPPORTED: &[&str] = &[
+    r"^branch\.(master|develop)$",
+    r"^branch\..+\.base$",
+    r"^prefix\.(feature|bugfix|release|hotfix|support|versiontag)$",
+    r"^path\.hooks$",
+    r"^(origin|upstream|archive|multi-hotfix)$",
+    r"^merge\.message$",
+    r"^type\..+\.(prefix|base|finishinto|tag|message|policy)$",
+    r"^[^.]+\.policy$",
+    r"^[^.]+\.start\.(base|fetch)$",
+    r"^[^.]+\.merge\.message$",
+    r"^[^.]+\.finish\.(backmerge|fetch|keep|keeplocal|keepremote|nobackmerge)$",
+    r"^(release|hotfix)\.finish\.(message|notag)$",
+];
+
+// the merge message when no template is configured
+const DEFAULT_MESSAGE: &str = "Merge branch '{branch}' into {base}";
+
+// merges as (from, into), in the order they are done
+type Merges = Vec<(String, String)>;
+
+// what the progress of fetches is handed to
+type Progress = Box<dyn Fn(&str)>;
+
+// oids are written as their hex sha
+fn serialize_oid<S: Serializer>(oid: &Oid, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_str(&oid.to_string())
+}
+
+/// The result of [`Flow::start`].
+#[derive(Clone, Debug, Serialize)]
+pub struct StartOutcome {
+    /// Full name of the created branch, e.g. `feature/x`.
+    pub branch: String,
+    /// The branch, tag or commit the new branch was created from.
+    pub base: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+    /// The linked worktree the branch was checked out in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+}
+
+/// The result of [`Flow::publish`] and [`Flow::track`].
+#[derive(Clone, Debug, Serialize)]
+pub struct RemoteOutcome {
+    pub branch: String,
+    pub remote: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+}
+
+/// The result of [`Flow::checkout`] and [`Flow::delete`].
+#[derive(Clone, Debug, Serialize)]
+pub struct BranchOutcome {
+    pub branch: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+    /// The other worktree checkout found the branch in, or the one
+    /// delete removed with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+}
+
+/// The result of [`Flow::rename`].
+#[derive(Clone, Debug, Serialize)]
+pub struct RenameOutcome {
+    pub from: String,
+    pub to: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+}
+
+/// The result of [`Flow::rebase`].
+#[derive(Clone, Debug, Serialize)]
+pub struct RebaseOutcome {
+    pub branch: String,
+    /// The branch it was rebased onto.
+    pub base: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+}
+
+/// The result of [`Flow::diff`].
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffOutcome {
+    pub branch: String,
+    pub base: String,
+    /// The paths changed.
+    pub files: Vec<String>,
+    /// The changes as a unified diff.
+    pub patch: String,
+}
+
+/// One merge done by [`Flow::finish`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Merge {
+    pub from: String,
+    pub into: String,
+    pub kind: MergeKind,
+    /// Tip of `into` after the merge.
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+}
+
+/// The result of [`Flow::finish`].
+#[derive(Clone, Debug, Serialize)]
+pub struct FinishOutcome {
+    pub branch: String,
+    pub merges: Vec<Merge>,
+    pub tag: Option<String>,
+    pub deleted: Vec<String>,
+    /// The linked worktree removed with the branch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+}
+
+/// One step of a [`Plan`].
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    Config {
+        key: String,
+        value: String,
+    },
+    Fetch {
+        remote: String,
+        branch: String,
+    },
+    Create {
+        branch: String,
+        base: String,
+    },
+    Checkout {
+        branch: String,
+    },
+    Merge {
+        from: String,
+        into: String,
+        kind: MergeKind,
+    },
+    Tag {
+        name: String,
+        branch: String,
+    },
+    Push {
+        remote: String,
+        branch: String,
+    },
+    SetUpstream {
+        branch: String,
+        remote: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    Delete {
+        branch: String,
+    },
+    Rebase {
+        branch: String,
+        onto: String,
+    },
+    /// Put a ref or a branch setting back to `to`, remove it when `None`.
+    Restore {
+        name: String,
+        to: Option<String>,
+    },
+    Pick {
+        commit: String,
+        onto: String,
+        kind: PickKind,
+    },
+    AddWorktree {
+        branch: String,
+        path: String,
+    },
+    RemoveWorktree {
+        path: String,
+    },
+}
+
+/// What a command would do, computed by the `plan_*` methods of [`Flow`]
+/// without touching refs or the working tree.
+#[derive(Clone, Debug, Serialize)]
+pub struct Plan {
+    /// The planned command, e.g. `finish`.
+    pub operation: String,
+    pub steps: Vec<Step>,
+}
+
+/// The result of [`Flow::undo`].
+#[derive(Clone, Debug, Serialize)]
+pub struct UndoOutcome {
+    /// The operation that was reverted.
+    pub undone: Entry,
+    /// The journal entry of the undo itself.
+    pub entry: Entry,
+}
+
+/// The tip of a deleted branch kept by [`Flow::finish`] or [`Flow::delete`].
+#[derive(Clone, Debug, Serialize)]
+pub struct ArchiveInfo {
+    pub branch: String,
+    /// Where it is kept, e.g. `refs/gitflow/archive/feature/x`.
+    pub refname: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+    /// When it was archived, in seconds since the unix epoch.
+    pub time: i64,
+}
+
+/// How a commit is (or would be) cherry-picked by [`Flow::backport`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickKind {
+    /// The branch already has the change.
+    Applied,
+    Clean,
+    Conflict,
+}
+
+/// What [`Flow::backport`] did on one support branch, commits are given
+/// as their hex sha.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackportLine {
+    pub branch: String,
+    /// The new commits, oldest first.
+    pub picked: Vec<String>,
+    /// Commits skipped because the branch already has their patch.
+    pub applied: Vec<String>,
+    /// The commit that didn't apply cleanly.
+    pub conflict: Option<String>,
+}
+
+/// The result of [`Flow::backport`] and of resuming or aborting it.
+#[derive(Clone, Debug, Serialize)]
+pub struct BackportOutcome {
+    /// The commit or tag given to backport.
+    pub source: String,
+    /// The commits it stands for, oldest first.
+    pub commits: Vec<String>,
+    pub lines: Vec<BackportLine>,
+    /// Support branches not backported to because of a conflict.
+    pub pending: Vec<String>,
+    /// Stopped at a conflict that waits to be resolved.
+    pub stopped: bool,
+}
+
+/// A branch reported by [`Flow::list`].
+#[derive(Clone, Debug, Serialize)]
+pub struct BranchInfo {
+    /// Full branch name, e.g. `feature/x`.
+    pub branch: String,
+    /// The name without its prefix, e.g. `x`.
+    pub name: String,
+    /// The branch it was started from if that is still there, else the
+    /// branch this type of branch starts from.
+    pub base: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+    /// Commits on the branch that are not on its base.
+    pub ahead: usize,
+    /// Commits on the base that are not on the branch.
+    pub behind: usize,
+    pub current: bool,
+}
+
+/// The git-flow settings reported by [`Flow::config`].
+#[derive(Clone, Debug, Serialize)]
+pub struct FlowConfig {
+    pub master: String,
+    pub develop: String,
+    /// Branch prefix of every branch type, keyed by type name.
+    pub prefixes: BTreeMap<String, String>,
+    pub versiontag: String,
+    pub hooks: String,
+    pub origin: String,
+    pub upstream: String,
+    /// `gitflow.*` settings that are set but not used, e.g. the squash
+    /// and rebase options of git-flow AVH.
+    pub unsupported: Vec<String>,
+}
+
+/// How far master or develop is from its remote counterpart.
+#[derive(Clone, Debug, Serialize)]
+pub struct SyncInfo {
+    pub branch: String,
+    #[serde(serialize_with = "serialize_oid")]
+    pub head: Oid,
+    /// The remote tracking branch, e.g. `origin/develop`.
+    pub upstream: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// The overview reported by [`Flow::status`].
+#[derive(Clone, Debug, Serialize)]
+pub struct FlowStatus {
+    /// `None` on a detached HEAD.
+    pub current: Option<String>,
+    /// Type of the current branch: a branch type name, `master` or `develop`.
+    pub current_type: Option<String>,
+    pub master: SyncInfo,
+    pub develop: SyncInfo,
+    /// Open branches keyed by type name.
+    pub branches: BTreeMap<String, Vec<BranchInfo>>,
+    /// An unfinished merge, rebase, cherry-pick, ...
+    pub in_progress: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+enum Head {
+    // the full ref name, e.g. refs/heads/develop
+    Branch(String),
+    Detached(Oid),
+}
+
+// a backport stopped at a conflict, kept in `gitflow/backport` of the
+// worktree's git directory
+#[derive(Serialize, Deserialize)]
+struct BackportState {
+    source: String,
+    commits: Vec<String>,
+    // support branches left, the first one is being picked onto
+    todo: Vec<String>,
+    // the commit of `commits` to pick next
+    at: usize,
+    lines: Vec<BackportLine>,
+    // where HEAD was, a full ref name or a commit
+    head: String,
+}
+
+impl BackportState {
+    fn outcome(self, stopped: bool) -> BackportOutcome {
+        BackportOutcome {
+            source: self.source,
+            commits: self.commits,
+            lines: self.lines,
+            pending: self.todo.into_iter().skip(1).collect(),
+            stopped,
+        }
+    }
+}
+
+// where the refs a command touches were before it started
+struct Rollback {
+    head: Head,
+    branches: Vec<(String, Oid)>,
+    // created by the command
+    tags: Vec<String>,
+    // written by the command, with what they held before
+    refs: Vec<(String, Option<Oid>)>,
+    // linked worktrees detached by the command, with their HEAD before
+    worktrees: Vec<(Worktree, String)>,
+}
+
+/// A git-flow enabled repository.
+///
+/// Nothing here prints or prompts, every operation reports what it did
+/// through its return value. The progress of fetches goes to the callback
+/// given to [`Flow::set_progress`], if any.
+pub struct Flow {
+    repo: GitcRepo,
+    remote: Option<String>,
+    progress: Option<Progress>,
+}
+
+impl From<GitcRepo> for Flow {
+    fn from(repo: GitcRepo) -> Self {
+        Self {
+            repo,
+            remote: None,
+            progress: None,
+        }
+    }
+}
+
+impl Flow {
+    /// Open the existing repository at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Flow::from(GitcRepo::open(path.as_ref())?))
+    }
+
+    /// Open the repository git would use from the current directory,
+    /// honoring `GIT_DIR`, `GIT_WORK_TREE` and `GIT_CEILING_DIRECTORIES`.
+    pub fn discover() -> Result<Self> {
+        Ok(Flow::from(GitcRepo::discover()?))
+    }
+
+    /// Open the repository at or above `path`, or create one at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Flow::from(GitcRepo::create(path.as_ref())?))
+    }
+
+    pub fn repo(&self) -> &GitcRepo {
+        &self.repo
+    }
+
+    /// Use `remote` for everything instead of the configured remotes.
+    pub fn set_remote(&mut self, remote: Option<String>) {
+        self.remote = remote;
+    }
+
+    /// Hand the progress of fetches to `progress`, as text meant to be
+    /// written to a terminal as is.
+    pub fn set_progress<F: Fn(&str) + 'static>(&mut self, progress: F) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    /// Write the git-flow configuration, creating the initial commit and
+    /// the develop branch when they are missing. Master and develop only
+    /// the upstream remote has, as in a fresh clone, are created from there
+    /// and track it.
+    pub fn init(&self, opts: &InitOptions) -> Result<()> {
+        self.repo.init()?;
+
+        for (key, value) in self.init_settings(opts)? {
+            self.repo.config(&key, &value)?;
+        }
+
+        let remote = self.init_remote(opts);
+        for branch in &[&opts.master, &opts.develop] {
+            if let Some(tracked) = self.remote_only(branch, &remote) {
+                self.repo.branch_from(branch, self.rev_head(&format!("refs/remotes/{}", tracked))?)?;
+                self.repo.set_tracking(branch, &remote)?;
+            }
+        }
+        if self.repo.0.find_branch(&opts.develop, BranchType::Local).is_err() {
+            self.repo.branch(&opts.develop)?;
+        }
+
+        Ok(())
+    }
+
+    /// The steps [`Flow::init`] would take.
+    pub fn plan_init(&self, opts: &InitOptions) -> Result<Plan> {
+        let mut steps = self.init_settings(opts)?
+            .into_iter()
+            .map(|(key, value)| Step::Config { key, value })
+            .collect::<Vec<_>>();
+        let remote = self.init_remote(opts);
+        for branch in &[&opts.master, &opts.develop] {
+            if let Some(tracked) = self.remote_only(branch, &remote) {
+                steps.push(Step::Create {
+                    branch: branch.to_string(),
+                    base: tracked,
+                });
+                steps.push(Step::SetUpstream {
+                    branch: branch.to_string(),
+                    remote: remote.clone(),
+                });
+            }
+        }
+        if self.repo.0.find_branch(&opts.develop, BranchType::Local).is_err()
+            && self.remote_only(&opts.develop, &remote).is_none() {
+            steps.push(Step::Create {
+                branch: opts.develop.clone(),
+                base: "HEAD".to_string(),
+            });
+        }
+
+        Ok(Plan {
+            operation: "init".to_string(),
+            steps,
+        })
+    }
+
+    /// The defaults of init: the current settings, from git config, as
+    /// left by git-flow AVH too, or the committed `.gitflow` file, and the
+    /// built-in ones for the rest.
+    pub fn init_options(&self) -> Result<InitOptions> {
+        let mut opts = InitOptions::default();
+        let fields = vec![
+            ("gitflow.branch.master", &mut opts.master),
+            ("gitflow.branch.develop", &mut opts.develop),
+            ("gitflow.prefix.feature", &mut opts.feature),
+            ("gitflow.prefix.bugfix", &mut opts.bugfix),
+            ("gitflow.prefix.release", &mut opts.release),
+            ("gitflow.prefix.hotfix", &mut opts.hotfix),
+            ("gitflow.prefix.support", &mut opts.support),
+            ("gitflow.prefix.versiontag", &mut opts.versiontag),
+            ("gitflow.origin", &mut opts.origin),
+        ];
+        for (key, field) in fields {
+            if let Ok(value) = self.repo.get_config(key) {
+                *field = value;
+            }
+        }
+        opts.hooks = self.repo.get_config("gitflow.path.hooks").ok();
+        opts.upstream = self.repo.get_config("gitflow.upstream").ok();
+
+        Ok(opts)
+    }
+
+    /// What an init left undone: the required settings that are missing,
+    /// and the develop branch, as `refs/heads/<develop>`, when init was
+    /// stopped before creating it or only the upstream remote has it, as
+    /// in a fresh clone. Empty once git-flow is initialized.
+    pub fn missing_settings(&self) -> Result<Vec<String>> {
+        let mut missing = REQUIRED.iter()
+            .filter(|key| !self.is_configured(key))
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        if let Ok(develop) = self.repo.get_config("gitflow.branch.develop") {
+            match self.remote_only(&develop, &self.upstream()?) {
+                Some(tracked) => missing.push(format!("refs/heads/{} (init creates it from {})", develop, tracked)),
+                None if self.repo.0.find_branch(&develop, BranchType::Local).is_err() => {
+                    missing.push(format!("refs/heads/{}", develop));
+                }
+                None => {}
+            }
+        }
+
+        Ok(missing)
+    }
+
+    // the remote init takes master and develop from, the upstream one
+    // once `opts` are written
+    fn init_remote(&self, opts: &InitOptions) -> String {
+        self.remote.clone()
+            .or_else(|| opts.upstream.clone())
+            .unwrap_or_else(|| opts.origin.clone())
+    }
+
+    // `<remote>/<branch>` when only `remote` has `branch`
+    fn remote_only(&self, branch: &str, remote: &str) -> Option<String> {
+        let repo = &self.repo.0;
+        let tracked = format!("{}/{}", remote, branch);
+        if repo.find_branch(branch, BranchType::Local).is_ok()
+            || repo.find_branch(&tracked, BranchType::Remote).is_err() {
+            return None;
+        }
+
+        Some(tracked)
+    }
+
+    /// Whether init ran to the end, see [`Flow::missing_settings`].
+    pub fn is_initialized(&self) -> Result<bool> {
+        Ok(self.missing_settings()?.is_empty())
+    }
+
+    /// Fail with [`Error::NotInitialized`] naming everything missing.
+    pub fn check_initialized(&self) -> Result<()> {
+        let missing = self.missing_settings()?;
+        if !missing.is_empty() {
+            return Err(Error::NotInitialized(missing.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `key` is set, in git config or the committed `.gitflow`.
+    pub fn is_configured(&self, key: &str) -> bool {
+        self.repo.get_config(key).is_ok()
+    }
+
+    // the config keys init writes, the ones the shared settings already
+    // have are left out so changes to `.gitflow` reach every clone
+    fn init_settings(&self, opts: &InitOptions) -> Result<Vec<(String, String)>> {
+        let hooks = match &opts.hooks {
+            Some(hooks) => hooks.clone(),
+            None => self.default_hooks_dir()?,
+        };
+        let mut settings = vec![
+            ("gitflow.branch.master", opts.master.clone()),
+            ("gitflow.branch.develop", opts.develop.clone()),
+            ("gitflow.prefix.feature", opts.feature.clone()),
+            ("gitflow.prefix.bugfix", opts.bugfix.clone()),
+            ("gitflow.prefix.release", opts.release.clone()),
+            ("gitflow.prefix.hotfix", opts.hotfix.clone()),
+            ("gitflow.prefix.support", opts.support.clone()),
+            ("gitflow.prefix.versiontag", opts.versiontag.clone()),
+            ("gitflow.path.hooks", hooks),
+            ("gitflow.origin", opts.origin.clone()),
+        ];
+        if let Some(upstream) = &opts.upstream {
+            settings.push(("gitflow.upstream", upstream.clone()));
+        }
+
+        let mut keep = Vec::new();
+        for (key, value) in settings {
+            if self.repo.get_shared(key)?.as_ref() != Some(&value) {
+                keep.push((key.to_string(), value));
+            }
+        }
+
+        Ok(keep)
+    }
+
+    pub fn default_hooks_dir(&self) -> Result<String> {
+        Ok(self.repo.hooks_dir().to_string_lossy().to_string())
+    }
+
+    // a setting written by init, missing means init never ran
+    fn flow_config(&self, name: &str) -> Result<String> {
+        match self.repo.get_config(name) {
+            Err(Error::Git(e)) if e.code() == ErrorCode::NotFound => {
+                Err(Error::NotInitialized(name.to_string()))
+            }
+            v => v,
+        }
+    }
+
+    pub fn master(&self) -> Result<String> {
+        self.flow_config("gitflow.branch.master")
+    }
+
+    pub fn develop(&self) -> Result<String> {
+        self.flow_config("gitflow.branch.develop")
+    }
+
+    /// The remote flow branches are published to, `gitflow.origin`.
+    pub fn origin(&self) -> Result<String> {
+        if let Some(remote) = &self.remote {
+            return Ok(remote.clone());
+        }
+        match self.repo.get_config("gitflow.origin") {
+            Ok(origin) => Ok(origin),
+            Err(_) => Ok("origin".to_string()),
+        }
+    }
+
+    /// The remote master and develop come from, `gitflow.upstream`, the
+    /// same as [`Flow::origin`] unless working from a fork.
+    pub fn upstream(&self) -> Result<String> {
+        if let Some(remote) = &self.remote {
+            return Ok(remote.clone());
+        }
+        match self.repo.get_config("gitflow.upstream") {
+            Ok(upstream) => Ok(upstream),
+            Err(_) => self.origin(),
+        }
+    }
+
+    // a setting of a user defined type, gitflow.type.<name>.<key>
+    fn type_config(&self, kind: &GfBranch, key: &str) -> Option<String> {
+        self.repo.get_config(&format!("gitflow.type.{}.{}", kind.name(), key)).ok()
+    }
+
+    /// The built-in branch types followed by the ones declared in the
+    /// config as `gitflow.type.<name>.<key>`.
+    pub fn branch_types(&self) -> Result<Vec<GfBranch>> {
+        let mut types = GfBranch::all().to_vec();
+
+        for key in self.repo.config_names(r"^gitflow\.type\..+\.[^.]+$")? {
+            let name = &key["gitflow.type.".len()..key.rfind('.').unwrap_or(key.len())];
+            let kind = GfBranch::Custom(name.to_string());
+            if GfBranch::from_name(name).is_none() && !types.contains(&kind) {
+                types.push(kind);
+            }
+        }
+
+        Ok(types)
+    }
+
+    pub fn prefix(&self, kind: &GfBranch) -> Result<String> {
+        match kind {
+            GfBranch::Custom(name) => {
+                Ok(self.type_config(kind, "prefix").unwrap_or_else(|| format!("{}/", name)))
+            }
+            // git-flow AVH before 1.9 and nvie have no bugfixes
+            GfBranch::Bugfix => self.repo.get_config("gitflow.prefix.bugfix").or_else(|_| {
+                self.master()?;
+                Ok("bugfix/".to_string())
+            }),
+            _ => self.flow_config(&format!("gitflow.prefix.{}", kind.name())),
+        }
+    }
+
+    /// The branch a type of branch starts from, `gitflow.<type>.start.base`
+    /// if set, else develop for features, bugfixes and releases, master for
+    /// hotfixes and supports.
+    pub fn base_of(&self, kind: &GfBranch) -> Result<String> {
+        if let Ok(base) = self.repo.get_config(&format!("gitflow.{}.start.base", kind.name())) {
+            return Ok(base);
+        }
+        match kind {
+            GfBranch::Feature | GfBranch::Bugfix | GfBranch::Release => self.develop(),
+            GfBranch::Hotfix | GfBranch::Support => self.master(),
+            GfBranch::Custom(_) => match self.type_config(kind, "base") {
+                Some(base) => Ok(base),
+                None => self.develop(),
+            },
+        }
+    }
+
+    // where a user defined type is merged on finish, its base by default.
+    // never empty, finish would delete the branch without merging it
+    fn finish_into(&self, kind: &GfBranch) -> Result<Vec<String>> {
+        let into = match self.type_config(kind, "finishInto") {
+            Some(into) => into.split(',')
+                .map(|b| b.trim().to_string())
+                .filter(|b| !b.is_empty())
+                .collect::<Vec<_>>(),
+            None => return Ok(vec![self.base_of(kind)?]),
+        };
+        if into.is_empty() {
+            return Err(Error::Generic(format!(
+                "gitflow.type.{}.finishInto names no branch", kind.name())));
+        }
+
+        Ok(into)
+    }
+
+    /// The full branch name of `name`, e.g. `feature/x` for `x`.
+    pub fn branch_name(&self, kind: &GfBranch, name: &str) -> Result<String> {
+        Ok(format!("{}{}", self.prefix(kind)?, name))
+    }
+
+    /// The naming policy of a type, a regex the names must match, from
+    /// `gitflow.<type>.policy`. `semver` is a shorthand for semantic versions.
+    pub fn policy(&self, kind: &GfBranch) -> Option<String> {
+        self.repo.get_config(&format!("gitflow.{}.policy", kind.name())).ok()
+            .or_else(|| match kind {
+                GfBranch::Custom(_) => self.type_config(kind, "policy"),
+                _ => None,
+            })
+    }
+
+    /// Check a new branch name against the ref format rules of git and the
+    /// naming policy of its type.
+    pub fn validate_name(&self, kind: &GfBranch, name: &str) -> Result<()> {
+        let branch = self.branch_name(kind, name)?;
+        for n in &[name, branch.as_str()] {
+            check_ref_format(n).map_err(|reason| Error::InvalidName {
+                name: n.to_string(),
+                reason,
+            })?;
+        }
+
+        if let Some(policy) = self.policy(kind) {
+            let pattern = if policy == "semver" { SEMVER } else { policy.as_str() };
+            let re = Regex::new(pattern).map_err(|e| Error::Generic(
+                    format!("gitflow.{}.policy is not a valid regex: {}", kind.name(), e)))?;
+            if !re.is_match(name) {
+                return Err(Error::InvalidName {
+                    name: name.to_string(),
+                    reason: format!("it doesn't match the {} naming policy `{}`", kind.name(), policy),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The type and name of a branch, `None` if it has no known prefix.
+    pub fn parse_branch(&self, branch: &str) -> Result<Option<(GfBranch, String)>> {
+        for kind in self.branch_types()? {
+            let prefix = self.prefix(&kind)?;
+            if !prefix.is_empty() && branch.starts_with(&prefix) {
+                let name = branch[prefix.len()..].to_string();
+                return Ok(Some((kind, name)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The type and name of the checked out branch.
+    pub fn current(&self) -> Result<(GfBranch, String)> {
+        let branch = get_branch_name(&self.repo.0)?;
+
+        self.parse_branch(&branch)?.ok_or_else(|| Error::Generic(format!(
+                    "{} is not a git-flow branch, name the branch to work on", branch)))
+    }
+
+    pub fn config(&self) -> Result<FlowConfig> {
+        let mut prefixes = BTreeMap::new();
+        for kind in self.branch_types()? {
+            prefixes.insert(kind.name().to_string(), self.prefix(&kind)?);
+        }
+
+        Ok(FlowConfig {
+            master: self.master()?,
+            develop: self.develop()?,
+            prefixes,
+            versiontag: self.flow_config("gitflow.prefix.versiontag")?,
+            // nvie git-flow doesn't set it
+            hooks: match self.repo.get_config("gitflow.path.hooks") {
+                Ok(hooks) => hooks,
+                Err(_) => self.default_hooks_dir()?,
+            },
+            origin: self.origin()?,
+            upstream: self.upstream()?,
+            unsupported: self.unsupported_settings()?,
+        })
+    }
+
+    /// The `gitflow.*` settings that are set but mean nothing here.
+    pub fn unsupported_settings(&self) -> Result<Vec<String>> {
+        let supported = SUPPORTED.iter()
+            .map(|p| Regex::new(&format!("(?i){}", p)))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Generic(e.to_string()))?;
+        let mut names = self.repo.config_names(r"^gitflow\.")?
+            .into_iter()
+            .filter(|n| !supported.iter().any(|re| re.is_match(&n["gitflow.".len()..])))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        Ok(names)
+    }
+
+    // a flag of git-flow AVH, `gitflow.<type>.<command>.<flag>`
+    fn avh_flag(&self, kind: &GfBranch, command: &str, flag: &str) -> bool {
+        self.repo.get_config_bool(&format!("gitflow.{}.{}.{}", kind.name(), command, flag))
+            .unwrap_or(false)
+    }
+
+    /// The defaults of start for `kind`, `gitflow.<type>.start.fetch` like
+    /// git-flow AVH keeps them.
+    pub fn start_options(&self, kind: &GfBranch) -> StartOptions {
+        StartOptions {
+            fetch: self.avh_flag(kind, "start", "fetch"),
+            ..Default::default()
+        }
+    }
+
+    /// The defaults of finish for `kind`, from the git-flow AVH settings
+    /// `gitflow.<type>.finish.fetch`, `keep`, `keeplocal`, `nobackmerge` and
+    /// `notag`. Remote branches are never deleted, `keepremote` holds anyway.
+    pub fn finish_options(&self, kind: &GfBranch) -> FinishOptions {
+        FinishOptions {
+            fetch: self.avh_flag(kind, "finish", "fetch"),
+            keep: self.avh_flag(kind, "finish", "keep") || self.avh_flag(kind, "finish", "keeplocal"),
+            no_backmerge: self.avh_flag(kind, "finish", "nobackmerge"),
+            no_tag: self.avh_flag(kind, "finish", "notag"),
+            ..Default::default()
+        }
+    }
+
+    /// All local branches of the given type.
+    pub fn list(&self, kind: &GfBranch) -> Result<Vec<BranchInfo>> {
+        let repo = &self.repo.0;
+        let prefix = self.prefix(kind)?;
+        let default_base = self.base_of(kind)?;
+        let mut branches = Vec::new();
+
+        for b in repo.branches(Some(BranchType::Local))? {
+            let (b, _) = b?;
+            let name = match b.name()? {
+                Some(name) if name.starts_with(&prefix) => name.to_string(),
+                _ => continue,
+            };
+            let head = match b.get().target() {
+                Some(head) => head,
+                None => continue,
+            };
+            // against the branch it was started from while that is open
+            let base = self.open_base(&name).unwrap_or_else(|| default_base.clone());
+            let (ahead, behind) = match repo.refname_to_id(&format!("refs/heads/{}", &base)) {
+                Ok(base_id) => repo.graph_ahead_behind(head, base_id)?,
+                Err(_) => (0, 0),
+            };
+
+            branches.push(BranchInfo {
+                name: name[prefix.len()..].to_string(),
+                branch: name,
+                base,
+                head,
+                ahead,
+                behind,
+                current: b.is_head(),
+            });
+        }
+        branches.sort_by(|a, b| a.branch.cmp(&b.branch));
+
+        Ok(branches)
+    }
+
+    fn sync_info(&self, branch: &str) -> Result<SyncInfo> {
+        let repo = &self.repo.0;
+        let head = repo.refname_to_id(&format!("refs/heads/{}", branch))
+            .map_err(|_| Error::BranchNotFound(branch.to_string()))?;
+        // the tracking branch, or the branch of the same name on the upstream remote
+        let tracking = self.repo.upstream(branch).or_else(|| {
+            let remote = self.upstream().ok()?;
+            let name = format!("{}/{}", remote, branch);
+            let oid = repo.refname_to_id(&format!("refs/remotes/{}", name)).ok()?;
+            Some((name, oid))
+        });
+        let (upstream, ahead, behind) = match tracking {
+            Some((name, oid)) => {
+                let (ahead, behind) = repo.graph_ahead_behind(head, oid)?;
+                (Some(name), ahead, behind)
+            }
+            None => (None, 0, 0),
+        };
+
+        Ok(SyncInfo {
+            branch: branch.to_string(),
+            head,
+            upstream,
+            ahead,
+            behind,
+        })
+    }
+
+    /// An overview of the whole flow: the current branch, master and
+    /// develop against their remotes and every open branch.
+    pub fn status(&self) -> Result<FlowStatus> {
+        let repo = &self.repo.0;
+        let master = self.sync_info(&self.master()?)?;
+        let develop = self.sync_info(&self.develop()?)?;
+
+        // no branch is HEAD when it is detached
+        let current = get_branch_name(repo).ok();
+        let current_type = match &current {
+            Some(c) if *c == master.branch => Some("master".to_string()),
+            Some(c) if *c == develop.branch => Some("develop".to_string()),
+            Some(c) => self.parse_branch(c)?.map(|(kind, _)| kind.name().to_string()),
+            None => None,
+        };
+
+        let mut branches = BTreeMap::new();
+        for kind in self.branch_types()? {
+            branches.insert(kind.name().to_string(), self.list(&kind)?);
+        }
+
+        let in_progress = match repo.state() {
+            _ if self.backport_state()?.is_some() => Some("backport"),
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some("merge"),
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert"),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("cherry-pick"),
+            RepositoryState::Bisect => Some("bisect"),
+            RepositoryState::Rebase
+                | RepositoryState::RebaseInteractive
+                | RepositoryState::RebaseMerge => Some("rebase"),
+            RepositoryState::ApplyMailbox
+                | RepositoryState::ApplyMailboxOrRebase => Some("am"),
+        }.map(|s| s.to_string());
+
+        let mut warnings = Vec::new();
+        for sync in &[&master, &develop] {
+            if let (Some(upstream), true) = (&sync.upstream, sync.behind > 0) {
+                warnings.push(format!("{} is {} commit(s) behind {}", sync.branch, sync.behind, upstream));
+            }
+        }
+        for kind in &[GfBranch::Release, GfBranch::Hotfix] {
+            let open = &branches[kind.name()];
+            if open.len() > 1 {
+                warnings.push(format!("{} {} branches are open: {}", open.len(), kind.name(),
+                        open.iter().map(|b| b.branch.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+        }
+        if let Some(op) = &in_progress {
+            warnings.push(format!("a {} is in progress", op));
+        }
+
+        Ok(FlowStatus {
+            current,
+            current_type,
+            master,
+            develop,
+            branches,
+            in_progress,
+            warnings,
+        })
+    }
+
+    /// Fetch `branch` from the upstream remote and make sure the local
+    /// one isn't behind it.
+    fn fetch_base(&self, branch: &str) -> Result<()> {
+        let repo = &self.repo.0;
+        let remote = self.upstream()?;
+        let remote_ref = format!("refs/remotes/{}/{}", remote, branch);
+
+        self.repo.fetch(&remote, &[&format!("+refs/heads/{}:{}", branch, remote_ref)], self.progress.as_deref())?;
+
+        let local = repo.refname_to_id(&format!("refs/heads/{}", branch))
+            .map_err(|_| Error::BranchNotFound(branch.to_string()))?;
+        let (_, behind) = repo.graph_ahead_behind(local, repo.refname_to_id(&remote_ref)?)?;
+        if behind > 0 {
+            return Err(Error::Generic(format!(
+                "{} is {} commit(s) behind {}/{}, pull it first", branch, behind, remote, branch)));
+        }
+
+        Ok(())
+    }
+
+    /// Push a branch to the origin remote and track it there.
+    pub fn publish(&self, kind: &GfBranch, name: &str) -> Result<RemoteOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let branch = self.branch_name(kind, name)?;
+        let remote = self.origin()?;
+        let head = self.repo.0.refname_to_id(&format!("refs/heads/{}", &branch))
+            .map_err(|_| Error::BranchNotFound(branch.clone()))?;
+
+        self.repo.push(&remote, &branch)?;
+        self.repo.set_tracking(&branch, &remote)?;
+
+        self.record(&format!("{} publish {}", kind.name(), name), &before)?;
+
+        Ok(RemoteOutcome {
+            branch,
+            remote,
+            head,
+        })
+    }
+
+    /// The steps [`Flow::publish`] would take.
+    pub fn plan_publish(&self, kind: &GfBranch, name: &str) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        let remote = self.origin()?;
+        self.branch_head(&branch)?;
+
+        Ok(Plan {
+            operation: "publish".to_string(),
+            steps: vec![
+                Step::Push {
+                    remote: remote.clone(),
+                    branch: branch.clone(),
+                },
+                Step::SetUpstream {
+                    branch,
+                    remote,
+                },
+            ],
+        })
+    }
+
+    /// Create a local branch from the one published on the origin remote.
+    pub fn track(&self, kind: &GfBranch, name: &str) -> Result<RemoteOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let repo = &self.repo.0;
+        let branch = self.branch_name(kind, name)?;
+        let remote = self.origin()?;
+        let remote_ref = format!("refs/remotes/{}/{}", remote, branch);
+
+        if repo.find_branch(&branch, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(branch));
+        }
+        self.repo.get_workdir()?;
+        self.repo.fetch(&remote, &[&format!("+refs/heads/{}:{}", branch, remote_ref)], self.progress.as_deref())?;
+
+        let head = repo.refname_to_id(&remote_ref)
+            .map_err(|_| Error::BranchNotFound(format!("{}/{}", remote, branch)))?;
+        repo.branch(&branch, &repo.find_commit(head)?, false)?;
+        self.repo.set_tracking(&branch, &remote)?;
+        self.repo.checkout(&branch)?;
+
+        self.record(&format!("{} track {}", kind.name(), name), &before)?;
+
+        Ok(RemoteOutcome {
+            branch,
+            remote,
+            head,
+        })
+    }
+
+    /// The steps [`Flow::track`] would take.
+    pub fn plan_track(&self, kind: &GfBranch, name: &str) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        let remote = self.origin()?;
+        if self.repo.0.find_branch(&branch, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(branch));
+        }
+
+        Ok(Plan {
+            operation: "track".to_string(),
+            steps: vec![
+                Step::Fetch {
+                    remote: remote.clone(),
+                    branch: branch.clone(),
+                },
+                Step::Create {
+                    branch: branch.clone(),
+                    base: format!("{}/{}", remote, branch),
+                },
+                Step::SetUpstream {
+                    branch: branch.clone(),
+                    remote,
+                },
+                Step::Checkout {
+                    branch,
+                },
+            ],
+        })
+    }
+
+    fn branch_head(&self, branch: &str) -> Result<Oid> {
+        self.repo.0.refname_to_id(&format!("refs/heads/{}", branch))
+            .map_err(|_| Error::BranchNotFound(branch.to_string()))
+    }
+
+    /// Switch to an existing branch.
+    pub fn checkout(&self, kind: &GfBranch, name: &str) -> Result<BranchOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let branch = self.branch_name(kind, name)?;
+        let head = self.branch_head(&branch)?;
+        // a branch checked out in another worktree is worked on over there
+        if let Some(tree) = self.other_worktree(&branch)? {
+            return Ok(BranchOutcome {
+                branch,
+                head,
+                worktree: Some(tree.path.to_string_lossy().to_string()),
+            });
+        }
+        self.repo.checkout(&branch)?;
+
+        self.record(&format!("{} checkout {}", kind.name(), name), &before)?;
+
+        Ok(BranchOutcome {
+            branch,
+            head,
+            worktree: None,
+        })
+    }
+
+    /// The steps [`Flow::checkout`] would take, none when the branch is
+    /// checked out in another worktree.
+    pub fn plan_checkout(&self, kind: &GfBranch, name: &str) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        self.branch_head(&branch)?;
+        let steps = match self.other_worktree(&branch)? {
+            Some(_) => Vec::new(),
+            None => vec![Step::Checkout { branch }],
+        };
+
+        Ok(Plan {
+            operation: "checkout".to_string(),
+            steps,
+        })
+    }
+
+    // the worktree `branch` is checked out in
+    fn worktree_of(&self, branch: &str) -> Result<Option<Worktree>> {
+        Ok(self.repo.worktrees()?.into_iter().find(|t| t.branch.as_deref() == Some(branch)))
+    }
+
+    // the worktree `branch` is checked out in, unless it is this one
+    fn other_worktree(&self, branch: &str) -> Result<Option<Worktree>> {
+        Ok(self.worktree_of(branch)?.filter(|t| !self.repo.is_current(t)))
+    }
+
+    // the linked worktree finish or delete remove with `branch`, after
+    // checking they can
+    fn removed_worktree(&self, branch: &str, force: bool) -> Result<Option<Worktree>> {
+        let tree = match self.worktree_of(branch)? {
+            Some(tree) if tree.linked => tree,
+            Some(tree) if !self.repo.is_current(&tree) => return Err(Error::Generic(format!(
+                        "'{}' is checked out in the main worktree {}, run the command there",
+                        branch, tree.path.display()))),
+            _ => return Ok(None),
+        };
+        self.repo.check_removable(&tree, force)?;
+
+        Ok(Some(tree))
+    }
+
+    /// Where `start` puts the worktree of `name` by default,
+    /// `../<repo>-<name>` next to the main worktree.
+    pub fn default_worktree(&self, name: &str) -> Result<PathBuf> {
+        // a bare repository stands in for the main worktree
+        let main = self.repo.worktrees()?.into_iter()
+            .find(|t| !t.linked)
+            .map_or_else(|| self.repo.common_dir(), |t| t.path);
+        let repo = main.file_name().map_or("repo".to_string(), |n| n.to_string_lossy().to_string());
+        let repo = repo.trim_end_matches(".git");
+
+        Ok(main.with_file_name(format!("{}-{}", repo, name.replace('/', "-"))))
+    }
+
+    // several hotfixes may be open at once, unless `gitflow.multi-hotfix`
+    // is turned off like git-flow AVH has it by default
+    fn check_multi_hotfix(&self, kind: &GfBranch) -> Result<()> {
+        if *kind != GfBranch::Hotfix || self.repo.get_config_bool("gitflow.multi-hotfix").unwrap_or(true) {
+            return Ok(());
+        }
+        match self.list(kind)?.first() {
+            Some(open) => Err(Error::Generic(format!(
+                        "the hotfix {} is open and gitflow.multi-hotfix is off, finish it first", open.name))),
+            None => Ok(()),
+        }
+    }
+
+    // the new worktree start checks the branch out in, if any
+    fn start_worktree(&self, name: &str, opts: &StartOptions) -> Result<Option<PathBuf>> {
+        if !opts.worktree {
+            return Ok(None);
+        }
+        let path = match &opts.worktree_path {
+            Some(path) => path.clone(),
+            None => self.default_worktree(name)?,
+        };
+        if path.exists() && fs::read_dir(&path)?.next().is_some() {
+            return Err(Error::Generic(format!("{} already exists and is not empty", path.display())));
+        }
+
+        Ok(Some(path))
+    }
+
+    /// What a branch changes since it forked from its base, like
+    /// `git diff <base>...<branch>`.
+    pub fn diff(&self, kind: &GfBranch, name: &str) -> Result<DiffOutcome> {
+        let repo = &self.repo.0;
+        let branch = self.branch_name(kind, name)?;
+        let head = self.branch_head(&branch)?;
+        let base = self.base_for(kind, &branch)?;
+        let fork = repo.merge_base(self.branch_head(&base)?, head)?;
+
+        let diff = repo.diff_tree_to_tree(
+            Some(&repo.find_commit(fork)?.tree()?),
+            Some(&repo.find_commit(head)?.tree()?),
+            None,
+        )?;
+        let files = diff.deltas()
+            .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let mut patch = String::new();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            if let '+' | '-' | ' ' = line.origin() {
+                patch.push(line.origin());
+            }
+            patch.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })?;
+
+        Ok(DiffOutcome {
+            branch,
+            base,
+            files,
+            patch,
+        })
+    }
+
+    /// Replay a branch on the tip of its base. Nothing changes when that
+    /// conflicts.
+    pub fn rebase(&self, kind: &GfBranch, name: &str) -> Result<RebaseOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let (branch, base) = self.rebase_onto(kind, name)?;
+
+        self.repo.rebase(&branch, &base)?;
+
+        self.record(&format!("{} rebase {}", kind.name(), name), &before)?;
+
+        Ok(RebaseOutcome {
+            head: self.branch_head(&branch)?,
+            branch,
+            base,
+        })
+    }
+
+    /// The steps [`Flow::rebase`] would take.
+    pub fn plan_rebase(&self, kind: &GfBranch, name: &str) -> Result<Plan> {
+        let (branch, onto) = self.rebase_onto(kind, name)?;
+
+        Ok(Plan {
+            operation: "rebase".to_string(),
+            steps: vec![Step::Rebase { branch, onto }],
+        })
+    }
+
+    // the branch to rebase and its base, checking both are there
+    fn rebase_onto(&self, kind: &GfBranch, name: &str) -> Result<(String, String)> {
+        let branch = self.branch_name(kind, name)?;
+        self.branch_head(&branch)?;
+        let base = self.base_for(kind, &branch)?;
+        self.branch_head(&base)?;
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+
+        Ok((branch, base))
+    }
+
+    /// Give a branch a new name, checked like the names of new branches.
+    pub fn rename(&self, kind: &GfBranch, name: &str, new_name: &str) -> Result<RenameOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let from = self.branch_name(kind, name)?;
+        let to = self.branch_name(kind, new_name)?;
+        self.validate_name(kind, new_name)?;
+
+        let mut b = self.repo.0.find_branch(&from, BranchType::Local)
+            .map_err(|_| Error::BranchNotFound(from.clone()))?;
+        if self.repo.0.find_branch(&to, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(to));
+        }
+        let renamed = b.rename(&to, false)?;
+        let head = renamed.get().target().ok_or(Error::NoHead)?;
+
+        self.record(&format!("{} rename {} {}", kind.name(), name, new_name), &before)?;
+
+        Ok(RenameOutcome {
+            from,
+            to,
+            head,
+        })
+    }
+
+    /// The steps [`Flow::rename`] would take.
+    pub fn plan_rename(&self, kind: &GfBranch, name: &str, new_name: &str) -> Result<Plan> {
+        let from = self.branch_name(kind, name)?;
+        let to = self.branch_name(kind, new_name)?;
+        self.validate_name(kind, new_name)?;
+        self.branch_head(&from)?;
+        if self.repo.0.find_branch(&to, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(to));
+        }
+
+        Ok(Plan {
+            operation: "rename".to_string(),
+            steps: vec![Step::Rename { from, to }],
+        })
+    }
+
+    // keep the tip of a branch about to be deleted, as set by
+    // `gitflow.archive`: `refs` (the default), `tags` or `none`
+    fn archive_branch(&self, branch: &str, head: Oid) -> Result<()> {
+        let msg = format!("git-flow: archive {}", branch);
+
+        match self.archive_ref(branch)? {
+            Some(refname) => match refname.strip_prefix("refs/tags/") {
+                Some(tag) => self.repo.keep_tag(tag, head, &msg),
+                None => self.repo.keep_ref(&refname, head, &msg),
+            },
+            None => Ok(()),
+        }
+    }
+
+    // the ref `archive_branch` keeps `branch` in, none for `none`
+    fn archive_ref(&self, branch: &str) -> Result<Option<String>> {
+        let style = self.repo.get_config("gitflow.archive").unwrap_or_else(|_| "refs".to_string());
+
+        match style.as_str() {
+            "refs" => Ok(Some(format!("{}{}", ARCHIVE_REFS, branch))),
+            "tags" => Ok(Some(format!("{}{}", ARCHIVE_TAGS, branch))),
+            "none" => Ok(None),
+            _ => Err(Error::Generic(format!(
+                "unknown gitflow.archive `{}`, use refs, tags or none", style))),
+        }
+    }
+
+    /// The archived tips of deleted branches, from both archive styles.
+    pub fn archives(&self) -> Result<Vec<ArchiveInfo>> {
+        let mut archives = Vec::new();
+
+        for prefix in &[ARCHIVE_REFS, ARCHIVE_TAGS] {
+            for r in self.repo.0.references_glob(&format!("{}*", prefix))? {
+                let r = r?;
+                let refname = match r.name() {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                archives.push(ArchiveInfo {
+                    branch: refname[prefix.len()..].to_string(),
+                    head: r.peel_to_commit()?.id(),
+                    time: self.repo.kept_since(&refname)?,
+                    refname,
+                });
+            }
+        }
+
+        Ok(archives)
+    }
+
+    fn archived(&self, branch: &str) -> Result<ArchiveInfo> {
+        self.archives()?
+            .into_iter()
+            .find(|a| a.branch == branch)
+            .ok_or_else(|| Error::BranchNotFound(format!("archive/{}", branch)))
+    }
+
+    /// Recreate a deleted branch from its archived tip.
+    pub fn restore(&self, kind: &GfBranch, name: &str) -> Result<BranchOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let branch = self.branch_name(kind, name)?;
+        let archive = self.archived(&branch)?;
+
+        self.repo.branch_from(&branch, archive.head)?;
+
+        self.record(&format!("{} restore {}", kind.name(), name), &before)?;
+
+        Ok(BranchOutcome {
+            branch,
+            head: archive.head,
+            worktree: None,
+        })
+    }
+
+    /// The steps [`Flow::restore`] would take.
+    pub fn plan_restore(&self, kind: &GfBranch, name: &str) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        let archive = self.archived(&branch)?;
+        if self.repo.0.find_branch(&branch, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(branch));
+        }
+
+        Ok(Plan {
+            operation: "restore".to_string(),
+            steps: vec![Step::Create {
+                branch,
+                base: archive.refname,
+            }],
+        })
+    }
+
+    /// Drop the archives older than `age` seconds.
+    pub fn prune_archives(&self, age: i64) -> Result<Vec<ArchiveInfo>> {
+        let pruned = self.old_archives(age)?;
+        for a in &pruned {
+            self.repo.0.find_reference(&a.refname)?.delete()?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// The steps [`Flow::prune_archives`] would take.
+    pub fn plan_prune_archives(&self, age: i64) -> Result<Plan> {
+        Ok(Plan {
+            operation: "archive prune".to_string(),
+            steps: self.old_archives(age)?
+                .into_iter()
+                .map(|a| Step::Restore {
+                    name: a.refname,
+                    to: None,
+                })
+                .collect(),
+        })
+    }
+
+    fn old_archives(&self, age: i64) -> Result<Vec<ArchiveInfo>> {
+        let cutoff = now() - age;
+
+        Ok(self.archives()?.into_iter().filter(|a| a.time < cutoff).collect())
+    }
+
+    /// Delete a branch without finishing it, refusing to lose commits that
+    /// are not merged yet, or changes in its linked worktree, unless `opts`
+    /// says so.
+    pub fn delete(&self, kind: &GfBranch, name: &str, opts: &DeleteOptions) -> Result<BranchOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let branch = self.branch_name(kind, name)?;
+        let head = self.branch_head(&branch)?;
+
+        if !opts.force {
+            self.check_merged(kind, &branch, head)?;
+        }
+        let worktree = self.removed_worktree(&branch, opts.force_worktree)?;
+        let archive = self.archive_ref(&branch)?;
+        let archived = archive.as_ref().and_then(|r| self.repo.0.refname_to_id(r).ok());
+        self.archive_branch(&branch, head)?;
+        // git refuses while the worktree has it checked out, which goes
+        // last, once the branch is gone
+        let old = match &worktree {
+            Some(tree) => Some(self.repo.set_worktree_head(tree, &head.to_string())?),
+            None => None,
+        };
+        if let Err(e) = self.repo.delete_branch(&branch) {
+            // the branch stays, so no archive of it
+            if let (Some(tree), Some(old)) = (&worktree, &old) {
+                self.repo.set_worktree_head(tree, old)?;
+            }
+            if let Some(refname) = &archive {
+                self.restore_ref(refname, archived)?;
+            }
+            return Err(e);
+        }
+
+        self.record(&format!("{} delete {}", kind.name(), name), &before)?;
+        if let Some(tree) = &worktree {
+            self.repo.remove_worktree(tree)?;
+        }
+
+        Ok(BranchOutcome {
+            branch,
+            head,
+            worktree: worktree.map(|t| t.path.to_string_lossy().to_string()),
+        })
+    }
+
+    /// The steps [`Flow::delete`] would take.
+    pub fn plan_delete(&self, kind: &GfBranch, name: &str, opts: &DeleteOptions) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        let head = self.branch_head(&branch)?;
+
+        if !opts.force {
+            self.check_merged(kind, &branch, head)?;
+        }
+        let mut steps = Vec::new();
+        if let Some(tree) = self.removed_worktree(&branch, opts.force_worktree)? {
+            steps.push(Step::RemoveWorktree { path: tree.path.to_string_lossy().to_string() });
+        }
+        steps.push(Step::Delete { branch });
+
+        Ok(Plan {
+            operation: "delete".to_string(),
+            steps,
+        })
+    }
+
+    // a branch is merged once the branch it finishes into contains it
+    fn check_merged(&self, kind: &GfBranch, branch: &str, head: Oid) -> Result<()> {
+        let into = match (kind, self.support_line(branch), self.open_base(branch)) {
+            (GfBranch::Hotfix, Some(support), _) => support,
+            (GfBranch::Feature, _, Some(base)) | (GfBranch::Bugfix, _, Some(base)) => base,
+            (GfBranch::Release, ..) | (GfBranch::Hotfix, ..) => self.master()?,
+            (GfBranch::Custom(_), ..) => self.finish_into(kind)?.remove(0),
+            _ => self.develop()?,
+        };
+        let into_head = self.branch_head(&into)?;
+        if head != into_head && !self.repo.0.graph_descendant_of(into_head, head)? {
+            return Err(Error::NotMerged {
+                branch: branch.to_string(),
+                into,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Create a new branch of type `kind` from its configured base, or
+    /// from the branch, tag or commit given in `opts`.
+    pub fn start(&self, kind: &GfBranch, name: &str, opts: &StartOptions) -> Result<StartOutcome> {
+        let before = RefState::read(&self.repo.0)?;
+        let repo = &self.repo.0;
+        let branch = self.branch_name(kind, name)?;
+        self.validate_name(kind, name)?;
+        let base = self.start_base(kind, opts)?;
+        // local branches can be brought up to date, anything else is taken as is
+        if opts.fetch && repo.find_branch(&base, BranchType::Local).is_ok() {
+            self.fetch_base(&base)?;
+        }
+        let base_commit = self.resolve_base(&base)?;
+        self.check_multi_hotfix(kind)?;
+        let worktree = self.start_worktree(name, opts)?;
+        // fail before the branch exists when it can't be checked out
+        if !opts.no_checkout && worktree.is_none() {
+            self.repo.get_workdir()?;
+        }
+
+        // create a new branch
+        self.repo.branch_from(&branch, base_commit.id())?;
+        if let Some(path) = &worktree {
+            if let Err(e) = self.repo.add_worktree(path, &branch) {
+                self.repo.delete_branch(&branch)?;
+                return Err(e);
+            }
+        }
+        // remember a branch it explicitly starts from, finish may go back there
+        if opts.base.is_some() && repo.find_branch(&base, BranchType::Local).is_ok() {
+            self.repo.config(&base_key(&branch), &base)?;
+        }
+        // and checkout it
+        if !opts.no_checkout && worktree.is_none() {
+            self.repo.checkout(&branch)?;
+        }
+
+        self.record(&format!("{} start {}", kind.name(), name), &before)?;
+
+        Ok(StartOutcome {
+            head: self.repo.0.refname_to_id(&format!("refs/heads/{}", &branch))?,
+            branch,
+            base,
+            worktree: worktree.map(|p| fs::canonicalize(&p).unwrap_or(p).to_string_lossy().to_string()),
+        })
+    }
+
+    /// The steps [`Flow::start`] would take.
+    pub fn plan_start(&self, kind: &GfBranch, name: &str, opts: &StartOptions) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        self.validate_name(kind, name)?;
+        let base = self.start_base(kind, opts)?;
+        if self.repo.0.find_branch(&branch, BranchType::Local).is_ok() {
+            return Err(Error::BranchExists(branch));
+        }
+        self.check_multi_hotfix(kind)?;
+
+        let mut steps = Vec::new();
+        if opts.fetch && self.repo.0.find_branch(&base, BranchType::Local).is_ok() {
+            steps.push(Step::Fetch {
+                remote: self.upstream()?,
+                branch: base.clone(),
+            });
+        }
+        self.resolve_base(&base)?;
+        let stored = opts.base.is_some() && self.repo.0.find_branch(&base, BranchType::Local).is_ok();
+        steps.push(Step::Create {
+            branch: branch.clone(),
+            base: base.clone(),
+        });
+        if stored {
+            steps.push(Step::Config {
+                key: base_key(&branch),
+                value: base,
+            });
+        }
+        if let Some(path) = self.start_worktree(name, opts)? {
+            steps.push(Step::AddWorktree {
+                branch,
+                path: path.to_string_lossy().to_string(),
+            });
+        } else if !opts.no_checkout {
+            steps.push(Step::Checkout { branch });
+        }
+
+        Ok(Plan {
+            operation: "start".to_string(),
+            steps,
+        })
+    }
+
+    fn start_base(&self, kind: &GfBranch, opts: &StartOptions) -> Result<String> {
+        match &opts.base {
+            Some(base) => Ok(base.clone()),
+            None => self.base_of(kind),
+        }
+    }
+
+    fn resolve_base(&self, base: &str) -> Result<Commit<'_>> {
+        self.repo.0.revparse_single(base)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|_| Error::InvalidBase(base.to_string()))
+    }
+
+    /// Merge the branch back, tag releases and hotfixes, then delete it.
+    ///
+    /// All or nothing: when a step fails, the branches, tags and HEAD are
+    /// put back where they were before the error is returned. A linked
+    /// worktree of the branch is removed last, once nothing else can fail.
+    pub fn finish(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<FinishOutcome> {
+        let branch = self.branch_name(kind, name)?;
+        if self.repo.0.find_branch(&branch, BranchType::Local).is_err() {
+            return Err(Error::BranchNotFound(branch));
+        }
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+
+        let (todo, tag_at) = self.finish_merges(kind, name, opts)?;
+        self.check_targets(&todo)?;
+        // a worktree of the branch goes with it, fail now if it can't
+        if !opts.keep {
+            self.removed_worktree(&branch, false)?;
+        }
+        if opts.fetch {
+            for into in merge_targets(&todo) {
+                self.fetch_base(into)?;
+            }
+        }
+
+        // from here on a failure puts every ref back where it was
+        let mut touched = vec![branch.as_str()];
+        touched.extend(merge_targets(&todo));
+        let mut rollback = self.snapshot(&touched)?;
+        let before = RefState::read(&self.repo.0)?;
+        match self.finish_steps(kind, name, &todo, tag_at, opts, &mut rollback) {
+            Ok((mut outcome, tree)) => {
+                self.record(&format!("{} finish {}", kind.name(), name), &before)?;
+                if let Some(tree) = tree {
+                    self.repo.remove_worktree(&tree)?;
+                    outcome.worktree = Some(tree.path.to_string_lossy().to_string());
+                }
+                Ok(outcome)
+            }
+            Err(e) => match self.rollback(&rollback) {
+                Ok(()) => Err(e),
+                Err(re) => Err(Error::Generic(format!("{}, and rolling back failed: {}", e, re))),
+            },
+        }
+    }
+
+    // the merges, tag and deletion of finish, recording what to undo, with
+    // the linked worktree left for finish to remove
+    fn finish_steps(
+        &self,
+        kind: &GfBranch,
+        name: &str,
+        todo: &[(String, String)],
+        tag_at: Option<usize>,
+        opts: &FinishOptions,
+        rollback: &mut Rollback,
+    ) -> Result<(FinishOutcome, Option<Worktree>)> {
+        let branch = self.branch_name(kind, name)?;
+        let mut merges = Vec::new();
+        let mut tag = None;
+
+        for (i, (from, into)) in todo.iter().enumerate() {
+            // the message is for the merge of the branch itself
+            let msg = if *from == branch {
+                Some(self.render_message(kind, name, into, opts)?)
+            } else {
+                None
+            };
+            merges.push(self.merge_into(from, into, msg.as_deref())?);
+            if tag_at == Some(i) {
+                let tagname = self.tag_finished(kind, into, name, opts)?;
+                rollback.tags.push(tagname.clone());
+                tag = Some(tagname);
+            }
+        }
+
+        // then archive and delete the git-flow branch. git refuses while a
+        // linked worktree has it checked out, so that one is detached
+        let mut deleted = Vec::new();
+        let mut worktree = None;
+        if !opts.keep {
+            let head = self.branch_head(&branch)?;
+            if let Some(refname) = self.archive_ref(&branch)? {
+                rollback.refs.push((refname.clone(), self.repo.0.refname_to_id(&refname).ok()));
+            }
+            self.archive_branch(&branch, head)?;
+            if let Some(tree) = self.worktree_of(&branch)?.filter(|t| t.linked) {
+                // work may have gone on there while the merges ran
+                self.repo.check_removable(&tree, false)?;
+                let old = self.repo.set_worktree_head(&tree, &head.to_string())?;
+                rollback.worktrees.push((tree.clone(), old));
+                worktree = Some(tree);
+            }
+            self.repo.delete_branch(&branch)?;
+            deleted.push(branch.clone());
+        }
+
+        Ok((FinishOutcome {
+            branch,
+            merges,
+            tag,
+            deleted,
+            worktree: None,
+        }, worktree))
+    }
+
+    // finish checks out the branches it merges into, which can't be
+    // checked out in another worktree at the same time
+    fn check_targets(&self, todo: &[(String, String)]) -> Result<()> {
+        for into in merge_targets(todo) {
+            if let Some(tree) = self.other_worktree(into)? {
+                return Err(Error::Generic(format!(
+                            "'{}' is checked out in {}, finish from there", into, tree.path.display())));
+            }
+        }
+
+        Ok(())
+    }
+
+    // remember HEAD and the tips of `branches`
+    fn snapshot(&self, branches: &[&str]) -> Result<Rollback> {
+        let repo = &self.repo.0;
+        let head = repo.find_reference("HEAD")?;
+        let head = match head.symbolic_target() {
+            Some(target) => Head::Branch(target.to_string()),
+            None => Head::Detached(head.target().ok_or(Error::NoHead)?),
+        };
+        let mut tips = Vec::new();
+        for b in branches {
+            tips.push((b.to_string(), self.branch_head(b)?));
+        }
+
+        Ok(Rollback {
+            head,
+            branches: tips,
+            tags: Vec::new(),
+            refs: Vec::new(),
+            worktrees: Vec::new(),
+        })
+    }
+
+    // put the refs of a snapshot back, recreating deleted branches and
+    // removing the tags created since, then reset the working tree. every
+    // step is tried, the errors are reported together
+    fn rollback(&self, rollback: &Rollback) -> Result<()> {
+        let repo = &self.repo.0;
+        let mut errors = Vec::new();
+
+        for (tree, head) in &rollback.worktrees {
+            attempt(&mut errors, self.repo.set_worktree_head(tree, head));
+        }
+        for (refname, oid) in &rollback.refs {
+            attempt(&mut errors, self.restore_ref(refname, *oid));
+        }
+        for tag in &rollback.tags {
+            attempt(&mut errors, repo.tag_delete(tag));
+        }
+        for (b, oid) in &rollback.branches {
+            attempt(&mut errors, repo.reference(&format!("refs/heads/{}", b), *oid, true, "git-flow: roll back"));
+        }
+        match &rollback.head {
+            Head::Branch(refname) => attempt(&mut errors, repo.set_head(refname)),
+            Head::Detached(oid) => attempt(&mut errors, repo.set_head_detached(*oid)),
+        }
+        attempt(&mut errors, repo.checkout_head(Some(CheckoutBuilder::new().force())));
+        attempt(&mut errors, repo.cleanup_state());
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Generic(errors.join(", ")))
+        }
+    }
+
+    // point `refname` back at `oid`, or remove it when it did not exist
+    fn restore_ref(&self, refname: &str, oid: Option<Oid>) -> Result<()> {
+        let repo = &self.repo.0;
+        match oid {
+            Some(oid) => {
+                repo.reference(refname, oid, true, "git-flow: roll back")?;
+            }
+            None => if let Ok(mut r) = repo.find_reference(refname) {
+                r.delete()?;
+            },
+        }
+
+        Ok(())
+    }
+
+    // shared by all the worktrees, like the refs it records
+    fn journal(&self) -> Journal {
+        Journal::new(&self.repo.common_dir())
+    }
+
+    // journal the refs `command` moved since `before`
+    fn record(&self, command: &str, before: &RefState) -> Result<()> {
+        let after = RefState::read(&self.repo.0)?;
+        self.journal().append(Entry::new(command, before, &after))?;
+
+        Ok(())
+    }
+
+    /// Every operation in the journal, oldest first.
+    pub fn log(&self) -> Result<Vec<Entry>> {
+        self.journal().entries()
+    }
+
+    // the entry undo would revert, checking its refs are still where it
+    // left them unless `force` is set
+    fn undo_entry(&self, force: bool) -> Result<Entry> {
+        let entry = self.journal().last_undoable()?
+            .ok_or_else(|| Error::Generic("nothing to undo".to_string()))?;
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+        if !force {
+            let now = RefState::read(&self.repo.0)?;
+            for r in &entry.refs {
+                if now.0.get(&r.name) != r.after.as_ref() {
+                    return Err(Error::Generic(format!(
+                        "{} moved since `{}`, use -f to undo it anyway", r.name, entry.command)));
+                }
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Revert the latest operation in the journal by putting the refs it
+    /// moved and the branch settings it changed back, deleting the branches
+    /// and tags it created. Remotes are left alone, operations that only
+    /// changed a remote are skipped.
+    pub fn undo(&self, force: bool) -> Result<UndoOutcome> {
+        let repo = &self.repo.0;
+        let undone = self.undo_entry(force)?;
+        let before = RefState::read(repo)?;
+        let msg = format!("git-flow: undo {}", undone.command);
+
+        // HEAD last, the branch it points to may only come back here
+        for r in undone.refs.iter().filter(|r| r.name != "HEAD") {
+            match &r.before {
+                Some(oid) => {
+                    repo.reference(&r.name, Oid::from_str(oid)?, true, &msg)?;
+                }
+                None => {
+                    if let Ok(mut reference) = repo.find_reference(&r.name) {
+                        reference.delete()?;
+                    }
+                }
+            }
+        }
+        // the base and upstream of the branches, which deleting them drops
+        for c in &undone.config {
+            match &c.before {
+                Some(value) => self.repo.config(&c.name, value)?,
+                None => match repo.config()?.remove(&c.name) {
+                    Err(e) if e.code() != ErrorCode::NotFound => return Err(e.into()),
+                    _ => {}
+                },
+            }
+        }
+        if let Some(head) = undone.refs.iter().find(|r| r.name == "HEAD").and_then(|r| r.before.as_ref()) {
+            if head.starts_with("refs/") {
+                repo.set_head(head)?;
+            } else {
+                repo.set_head_detached(Oid::from_str(head)?)?;
+            }
+        }
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+
+        let after = RefState::read(repo)?;
+        let mut entry = Entry::new("undo", &before, &after);
+        entry.undoes = Some(undone.id);
+
+        Ok(UndoOutcome {
+            entry: self.journal().append(entry)?,
+            undone,
+        })
+    }
+
+    /// The steps [`Flow::undo`] would take.
+    pub fn plan_undo(&self, force: bool) -> Result<Plan> {
+        let undone = self.undo_entry(force)?;
+
+        Ok(Plan {
+            operation: format!("undo `{}`", undone.command),
+            steps: undone.refs.into_iter()
+                .chain(undone.config)
+                .map(|r| Step::Restore {
+                    name: r.name,
+                    to: r.before,
+                })
+                .collect(),
+        })
+    }
+
+    /// Cherry-pick a commit onto support branches, all of them when `lines`
+    /// is empty. A merge, e.g. the tag of a finished hotfix, stands for the
+    /// commits it brought in. Commits a branch already has are skipped by
+    /// their patch id, new ones note where they came from like
+    /// `git cherry-pick -x` does.
+    ///
+    /// A conflict stops the backport with the branch checked out and the
+    /// conflict in the working tree, see [`Flow::backport_continue`] and
+    /// [`Flow::backport_abort`].
+    pub fn backport(&self, source: &str, lines: &[String]) -> Result<BackportOutcome> {
+        if self.backport_state()?.is_some() {
+            return Err(Error::Generic("a backport is in progress, use --continue or --abort".to_string()));
+        }
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+        let before = RefState::read(&self.repo.0)?;
+        let state = BackportState {
+            source: source.to_string(),
+            commits: self.backport_commits(source)?.iter().map(|c| c.to_string()).collect(),
+            todo: self.backport_lines(lines)?,
+            at: 0,
+            lines: Vec::new(),
+            head: self.head_name()?,
+        };
+
+        let outcome = self.run_backport(state)?;
+        self.record(&format!("backport {}", source), &before)?;
+
+        Ok(outcome)
+    }
+
+    /// Commit the resolved conflict of a stopped backport and carry on with
+    /// the rest. A resolution that changes nothing skips the commit.
+    pub fn backport_continue(&self) -> Result<BackportOutcome> {
+        let repo = &self.repo.0;
+        let mut state = self.backport_state()?
+            .ok_or_else(|| Error::Generic("no backport in progress".to_string()))?;
+        let mut index = repo.index()?;
+        if index.has_conflicts() {
+            return Err(Error::Generic("resolve the conflicts and stage the files first".to_string()));
+        }
+        let before = RefState::read(repo)?;
+
+        let branch = state.todo[0].clone();
+        let commit = Oid::from_str(&state.commits[state.at])?;
+        let head = self.branch_head(&branch)?;
+        let tree = index.write_tree()?;
+        let line = state.lines.last_mut()
+            .ok_or_else(|| Error::Generic("the backport state is corrupted".to_string()))?;
+        line.conflict = None;
+        if tree == repo.find_commit(head)?.tree_id() {
+            line.applied.push(commit.to_string());
+        } else {
+            let picked = self.repo.pick_commit(commit, head, tree)?;
+            self.move_branch(&branch, picked)?;
+            line.picked.push(picked.to_string());
+        }
+        repo.cleanup_state()?;
+        state.at += 1;
+
+        let outcome = self.run_backport(state)?;
+        self.record("backport --continue", &before)?;
+
+        Ok(outcome)
+    }
+
+    /// Give up a stopped backport, dropping the conflicting pick and going
+    /// back to where HEAD was. What was picked so far stays.
+    pub fn backport_abort(&self) -> Result<BackportOutcome> {
+        let repo = &self.repo.0;
+        let state = self.backport_state()?
+            .ok_or_else(|| Error::Generic("no backport in progress".to_string()))?;
+        let before = RefState::read(repo)?;
+
+        repo.reset(&repo.head()?.peel(ObjectType::Commit)?, ResetType::Hard, None)?;
+        repo.cleanup_state()?;
+        self.clear_backport()?;
+        if self.head_name()? != state.head {
+            self.return_to(&state.head)?;
+        }
+        self.record("backport --abort", &before)?;
+
+        Ok(state.outcome(false))
+    }
+
+    /// The steps [`Flow::backport`] would take. Picks are tried in a
+    /// scratch repository, nothing is written to this one, so a conflict
+    /// shows up as the last step.
+    pub fn plan_backport(&self, source: &str, lines: &[String]) -> Result<Plan> {
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+        let commits = self.backport_commits(source)?;
+        // the picks are committed in a scratch repository
+        let scratch = self.repo.scratch()?;
+        let mut steps = Vec::new();
+
+        for branch in self.backport_lines(lines)? {
+            let mut head = self.branch_head(&branch)?;
+            for &commit in &commits {
+                let kind = if self.has_patch(&scratch, head, commit)? {
+                    PickKind::Applied
+                } else {
+                    match scratch.pick_tree(commit, head)? {
+                        Some(tree) => {
+                            head = scratch.pick_commit(commit, head, tree)?;
+                            PickKind::Clean
+                        }
+                        None => PickKind::Conflict,
+                    }
+                };
+                steps.push(Step::Pick {
+                    commit: commit.to_string(),
+                    onto: branch.clone(),
+                    kind,
+                });
+                // backport stops at the first conflict
+                if kind == PickKind::Conflict {
+                    return Ok(Plan {
+                        operation: "backport".to_string(),
+                        steps,
+                    });
+                }
+            }
+        }
+
+        Ok(Plan {
+            operation: "backport".to_string(),
+            steps,
+        })
+    }
+
+    // pick what is left of a backport, stopping at the first conflict
+    fn run_backport(&self, mut state: BackportState) -> Result<BackportOutcome> {
+        let commits = state.commits.iter()
+            .map(|c| Oid::from_str(c))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        while let Some(branch) = state.todo.first().cloned() {
+            // a resumed branch carries on with what it did so far
+            let mut line = match state.lines.pop() {
+                Some(line) if line.branch == branch => line,
+                last => {
+                    state.lines.extend(last);
+                    BackportLine {
+                        branch: branch.clone(),
+                        picked: Vec::new(),
+                        applied: Vec::new(),
+                        conflict: None,
+                    }
+                }
+            };
+
+            let mut head = self.branch_head(&branch)?;
+            for (i, &commit) in commits.iter().enumerate().skip(state.at) {
+                if self.has_patch(&self.repo, head, commit)? {
+                    line.applied.push(commit.to_string());
+                    continue;
+                }
+                match self.repo.pick_tree(commit, head)? {
+                    Some(tree) => {
+                        head = self.repo.pick_commit(commit, head, tree)?;
+                        line.picked.push(head.to_string());
+                    }
+                    None => {
+                        // leave the conflict to be resolved on the branch
+                        self.move_branch(&branch, head)?;
+                        self.repo.checkout(&branch)?;
+                        self.repo.cherrypick_conflicts(commit)?;
+                        line.conflict = Some(commit.to_string());
+                        state.at = i;
+                        state.lines.push(line);
+                        self.save_backport(&state)?;
+                        return Ok(state.outcome(true));
+                    }
+                }
+            }
+
+            self.move_branch(&branch, head)?;
+            state.todo.remove(0);
+            state.at = 0;
+            state.lines.push(line);
+        }
+
+        self.clear_backport()?;
+        if self.head_name()? != state.head {
+            self.return_to(&state.head)?;
+        }
+
+        Ok(state.outcome(false))
+    }
+
+    // the commits `source` stands for, oldest first: the ones a merge
+    // brought in, the ones since the version before for the tag of a
+    // fast-forwarded finish, or the commit itself
+    fn backport_commits(&self, source: &str) -> Result<Vec<Oid>> {
+        let repo = &self.repo.0;
+        let commit = self.resolve_base(source)?;
+        let since = if commit.parent_count() > 1 {
+            commit.parent_id(0)?
+        } else if source.starts_with("refs/tags/") || repo.find_reference(&format!("refs/tags/{}", source)).is_ok() {
+            self.previous_version(&commit)?.ok_or_else(|| Error::Generic(format!(
+                        "no version is tagged before '{}', backport its commits one by one", source)))?
+        } else {
+            return Ok(vec![commit.id()]);
+        };
+
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE);
+        walk.push(commit.id())?;
+        walk.hide(since)?;
+        let mut commits = Vec::new();
+        for oid in walk {
+            let oid = oid?;
+            if repo.find_commit(oid)?.parent_count() == 1 {
+                commits.push(oid);
+            }
+        }
+
+        Ok(commits)
+    }
+
+    // the nearest first parent of `commit` with a version tag
+    fn previous_version(&self, commit: &Commit) -> Result<Option<Oid>> {
+        let repo = &self.repo.0;
+        let prefix = format!("refs/tags/{}", self.flow_config("gitflow.prefix.versiontag")?);
+        let mut tagged = Vec::new();
+        for r in repo.references_glob(&format!("{}*", prefix))? {
+            let r = r?;
+            if r.name().is_some_and(|n| !n.starts_with(ARCHIVE_TAGS)) {
+                tagged.push(r.peel_to_commit()?.id());
+            }
+        }
+
+        let mut walk = repo.revwalk()?;
+        walk.simplify_first_parent();
+        walk.push(commit.id())?;
+        for oid in walk.skip(1) {
+            let oid = oid?;
+            if tagged.contains(&oid) {
+                return Ok(Some(oid));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // the support branches to backport to, by name or full branch name
+    fn backport_lines(&self, lines: &[String]) -> Result<Vec<String>> {
+        if lines.is_empty() {
+            let all = self.list(&GfBranch::Support)?
+                .into_iter()
+                .map(|b| b.branch)
+                .collect::<Vec<_>>();
+            if all.is_empty() {
+                return Err(Error::Generic("there are no support branches to backport to".to_string()));
+            }
+            return Ok(all);
+        }
+
+        let prefix = self.prefix(&GfBranch::Support)?;
+        lines.iter()
+            .map(|line| {
+                let branch = if line.starts_with(&prefix) {
+                    line.clone()
+                } else {
+                    self.branch_name(&GfBranch::Support, line)?
+                };
+                self.branch_head(&branch)?;
+                Ok(branch)
+            })
+            .collect()
+    }
+
+    // whether `head` has the change of `commit` already, the commit itself
+    // or a cherry-pick of it. `head` may only be in `in_repo`, a scratch
+    // repository of a plan
+    fn has_patch(&self, in_repo: &GitcRepo, head: Oid, commit: Oid) -> Result<bool> {
+        let repo = &in_repo.0;
+        if head == commit || repo.graph_descendant_of(head, commit)? {
+            return Ok(true);
+        }
+
+        let id = in_repo.patch_id(commit)?;
+        let mut walk = repo.revwalk()?;
+        walk.push(head)?;
+        walk.hide(commit)?;
+        for oid in walk {
+            let oid = oid?;
+            if repo.find_commit(oid)?.parent_count() == 1 && in_repo.patch_id(oid)? == id {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // point `branch` at `oid`, updating the working tree when it is
+    // checked out
+    fn move_branch(&self, branch: &str, oid: Oid) -> Result<()> {
+        let repo = &self.repo.0;
+        let refname = format!("refs/heads/{}", branch);
+        if repo.refname_to_id(&refname)? == oid {
+            return Ok(());
+        }
+
+        repo.reference(&refname, oid, true, "git-flow: backport")?;
+        if self.head_name()? == refname {
+            repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+        }
+
+        Ok(())
+    }
+
+    // the branch HEAD points to as a full ref name, or the detached commit
+    fn head_name(&self) -> Result<String> {
+        let head = self.repo.0.find_reference("HEAD")?;
+        match head.symbolic_target() {
+            Some(target) => Ok(target.to_string()),
+            None => Ok(head.target().ok_or(Error::NoHead)?.to_string()),
+        }
+    }
+
+    // switch back to a HEAD returned by head_name
+    fn return_to(&self, head: &str) -> Result<()> {
+        match head.strip_prefix("refs/heads/") {
+            Some(branch) => self.repo.checkout(branch),
+            None => {
+                let repo = &self.repo.0;
+                repo.set_head_detached(Oid::from_str(head)?)?;
+                repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+                Ok(())
+            }
+        }
+    }
+
+    // a backport works in one worktree, like git's own cherry-pick state
+    // its state stays in that worktree's git directory
+    fn backport_path(&self) -> PathBuf {
+        self.repo.0.path().join("gitflow").join("backport")
+    }
+
+    fn backport_state(&self) -> Result<Option<BackportState>> {
+        let path = self.backport_path();
+        match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).map(Some).map_err(|e| Error::Generic(
+                    format!("{} is corrupted: {}", path.display(), e))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_backport(&self, state: &BackportState) -> Result<()> {
+        let path = self.backport_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(state).map_err(|e| Error::Generic(e.to_string()))?)?;
+
+        Ok(())
+    }
+
+    fn clear_backport(&self) -> Result<()> {
+        match fs::remove_file(self.backport_path()) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The steps [`Flow::finish`] would take. Merges are tried in a
+    /// scratch repository, nothing is written to this one, so a conflict
+    /// shows up as the last step.
+    pub fn plan_finish(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<Plan> {
+        let branch = self.branch_name(kind, name)?;
+        self.branch_head(&branch)?;
+        if !self.repo.is_clean()? {
+            return Err(Error::DirtyWorkingTree);
+        }
+
+        let (todo, tag_at) = self.finish_merges(kind, name, opts)?;
+        let mut steps = Vec::new();
+        if opts.fetch {
+            let remote = self.upstream()?;
+            for into in merge_targets(&todo) {
+                steps.push(Step::Fetch {
+                    remote: remote.clone(),
+                    branch: into.to_string(),
+                });
+            }
+        }
+
+        // where every branch would be after the merges so far, the merges
+        // are committed in a scratch repository
+        let scratch = self.repo.scratch()?;
+        let mut heads = BTreeMap::new();
+        for (i, (from, into)) in todo.iter().enumerate() {
+            let from_head = match heads.get(from) {
+                Some(oid) => *oid,
+                None => self.rev_head(from)?,
+            };
+            let into_head = match heads.get(into) {
+                Some(oid) => *oid,
+                None => self.branch_head(into)?,
+            };
+            let (merge_kind, head) = scratch.merge_preview(into_head, from_head)?;
+            steps.push(Step::Merge {
+                from: from.clone(),
+                into: into.clone(),
+                kind: merge_kind,
+            });
+            match head {
+                Some(head) => heads.insert(into.clone(), head),
+                // finish stops at the first conflict
+                None => return Ok(Plan {
+                    operation: "finish".to_string(),
+                    steps,
+                }),
+            };
+            if tag_at == Some(i) {
+                let tagname = self.finish_tag(name, opts)?;
+                if self.repo.0.find_reference(&format!("refs/tags/{}", tagname)).is_ok() {
+                    return Err(Error::TagExists(tagname));
+                }
+                // the tag may be merged back later on
+                heads.insert(format!("refs/tags/{}", tagname), heads[into]);
+                steps.push(Step::Tag {
+                    name: tagname,
+                    branch: into.clone(),
+                });
+            }
+        }
+        self.check_targets(&todo)?;
+        if !opts.keep {
+            if let Some(tree) = self.removed_worktree(&branch, false)? {
+                steps.push(Step::RemoveWorktree { path: tree.path.to_string_lossy().to_string() });
+            }
+            steps.push(Step::Delete { branch });
+        }
+
+        Ok(Plan {
+            operation: "finish".to_string(),
+            steps,
+        })
+    }
+
+    // the merges finishing a branch takes as (from, into) in order, and the
+    // index of the merge whose target gets tagged. `from` is a branch name
+    // or the full name of a tag
+    fn finish_merges(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<(Merges, Option<usize>)> {
+        let branch = self.branch_name(kind, name)?;
+        // tagged once, where it lands first
+        let tag_at = if self.tags_on_finish(kind) && !opts.no_tag { Some(0) } else { None };
+
+        // a hotfix of a support line only goes back there
+        if let (GfBranch::Hotfix, Some(support)) = (kind, self.support_line(&branch)) {
+            return Ok((vec![(branch, support)], tag_at));
+        }
+        // and a feature or bugfix to the branch it started from, e.g. the
+        // release it fixes, while that is open
+        if let GfBranch::Feature | GfBranch::Bugfix = kind {
+            if let Some(base) = self.open_base(&branch) {
+                return Ok((vec![(branch, base)], tag_at));
+            }
+        }
+
+        match kind {
+            GfBranch::Release | GfBranch::Hotfix => {
+                let mut merges = vec![(branch.clone(), self.master()?)];
+                if !opts.no_backmerge {
+                    let into = match kind {
+                        GfBranch::Hotfix => self.hotfix_target(opts)?,
+                        _ => self.develop()?,
+                    };
+                    merges.push((self.backmerge_source(kind, name, opts)?, into));
+                }
+                Ok((merges, tag_at))
+            },
+            GfBranch::Custom(_) => {
+                Ok((self.finish_into(kind)?
+                        .into_iter()
+                        .map(|into| (branch.clone(), into))
+                        .collect(),
+                    tag_at))
+            },
+            _ => Ok((vec![(branch, self.develop()?)], tag_at)),
+        }
+    }
+
+    /// The branch `branch` was explicitly started from, see
+    /// [`StartOptions::base`].
+    pub fn stored_base(&self, branch: &str) -> Option<String> {
+        self.repo.get_config(&base_key(branch))
+            .or_else(|_| self.repo.get_config(&avh_base_key(branch)))
+            .ok()
+    }
+
+    // the branch `branch` of type `kind` is based on: the one it was
+    // started from while that exists, the base of its type otherwise
+    fn base_for(&self, kind: &GfBranch, branch: &str) -> Result<String> {
+        match self.open_base(branch) {
+            Some(base) => Ok(base),
+            None => self.base_of(kind),
+        }
+    }
+
+    // the stored base of `branch` if it still exists
+    fn open_base(&self, branch: &str) -> Option<String> {
+        self.stored_base(branch)
+            .filter(|base| self.repo.0.find_branch(base, BranchType::Local).is_ok())
+    }
+
+    // the support branch a hotfix was started from
+    fn support_line(&self, branch: &str) -> Option<String> {
+        let base = self.stored_base(branch)?;
+        match self.parse_branch(&base) {
+            Ok(Some((GfBranch::Support, _))) => Some(base),
+            _ => None,
+        }
+    }
+
+    // what goes back to develop after a release or hotfix landed on
+    // master, by `gitflow.<type>.finish.backmerge`: the new tag (the
+    // default), the branch itself, or all of master
+    fn backmerge_source(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<String> {
+        let key = format!("gitflow.{}.finish.backmerge", kind.name());
+
+        match self.repo.get_config(&key).unwrap_or_else(|_| "tag".to_string()).as_str() {
+            // the branch stands in for a tag not made
+            "tag" if opts.no_tag => self.branch_name(kind, name),
+            "tag" => Ok(format!("refs/tags/{}", self.finish_tag(name, opts)?)),
+            "branch" => self.branch_name(kind, name),
+            "master" => self.master(),
+            other => Err(Error::Generic(format!("unknown {} `{}`, use tag, branch or master", key, other))),
+        }
+    }
+
+    // a finished hotfix goes into the open release, which takes it to
+    // develop when it finishes, and into develop when there is none
+    fn hotfix_target(&self, opts: &FinishOptions) -> Result<String> {
+        if opts.no_release {
+            return self.develop();
+        }
+        if let Some(release) = &opts.release {
+            let branch = self.branch_name(&GfBranch::Release, release)?;
+            self.branch_head(&branch)?;
+            return Ok(branch);
+        }
+
+        let mut open = self.list(&GfBranch::Release)?;
+        match open.len() {
+            0 => self.develop(),
+            1 => Ok(open.remove(0).branch),
+            _ => Err(Error::Generic(format!(
+                "several releases are open ({}), pick one with --release or use --no-release",
+                open.iter().map(|b| b.name.as_str()).collect::<Vec<_>>().join(", ")))),
+        }
+    }
+
+    /// Whether finishing a branch of type `kind` tags it.
+    pub fn tags_on_finish(&self, kind: &GfBranch) -> bool {
+        match kind {
+            GfBranch::Release | GfBranch::Hotfix => true,
+            GfBranch::Custom(_) => self.repo.get_config_bool(&format!("gitflow.type.{}.tag", kind.name()))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// The message of the merge finishing a branch into its first target,
+    /// what an editor is prefilled with.
+    pub fn merge_message(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<String> {
+        let (todo, _) = self.finish_merges(kind, name, opts)?;
+
+        self.render_message(kind, name, &todo[0].1, opts)
+    }
+
+    // the message of merging a branch into `into`: the one in `opts`, or
+    // the template in `gitflow.<type>.merge.message` or
+    // `gitflow.merge.message`, with its placeholders filled in
+    fn render_message(&self, kind: &GfBranch, name: &str, into: &str, opts: &FinishOptions) -> Result<String> {
+        let template = match &opts.message {
+            Some(m) if !m.is_empty() => m.clone(),
+            _ => self.repo.get_config(&format!("gitflow.{}.merge.message", kind.name()))
+                .ok()
+                .or_else(|| match kind {
+                    GfBranch::Custom(_) => self.type_config(kind, "message"),
+                    _ => None,
+                })
+                .or_else(|| self.repo.get_config("gitflow.merge.message").ok())
+                .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+        };
+
+        self.fill_placeholders(&template, kind, name, into, opts)
+    }
+
+    // the message of the tag of a finished branch on `into`: the one in
+    // `opts`, the template in `gitflow.<type>.finish.message`, or the tag
+    fn render_tag_message(&self, kind: &GfBranch, name: &str, into: &str, opts: &FinishOptions) -> Result<String> {
+        let template = match &opts.tag_message {
+            Some(m) if !m.is_empty() => m.clone(),
+            _ => self.repo.get_config(&format!("gitflow.{}.finish.message", kind.name()))
+                .unwrap_or_else(|_| "{version}".to_string()),
+        };
+
+        self.fill_placeholders(&template, kind, name, into, opts)
+    }
+
+    fn fill_placeholders(&self, template: &str, kind: &GfBranch, name: &str, into: &str, opts: &FinishOptions) -> Result<String> {
+        let version = if self.tags_on_finish(kind) {
+            self.finish_tag(name, opts)?
+        } else {
+            name.to_string()
+        };
+
+        Ok(template
+            .replace("{branch}", &self.branch_name(kind, name)?)
+            .replace("{type}", kind.name())
+            .replace("{name}", name)
+            .replace("{base}", into)
+            .replace("{version}", &version))
+    }
+
+    /// The editor git would use: `GIT_EDITOR`, `core.editor`, `VISUAL`,
+    /// `EDITOR`, then `vi`.
+    pub fn editor(&self) -> String {
+        env::var("GIT_EDITOR").ok()
+            .or_else(|| self.repo.get_config("core.editor").ok())
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .filter(|e| !e.is_empty())
+            .unwrap_or_else(|| "vi".to_string())
+    }
+
+    /// The tag finishing `name` creates, the one in `opts` or the version
+    /// tag prefix followed by `name`.
+    pub fn finish_tag(&self, name: &str, opts: &FinishOptions) -> Result<String> {
+        match &opts.tag {
+            Some(t) => Ok(t.clone()),
+            None => Ok(format!("{}{}", self.flow_config("gitflow.prefix.versiontag")?, name)),
+        }
+    }
+
+    // tag the tip of `branch` for the finished `name`
+    fn tag_finished(&self, kind: &GfBranch, branch: &str, name: &str, opts: &FinishOptions) -> Result<String> {
+        let tagname = self.finish_tag(name, opts)?;
+        self.repo.tag(
+            self.repo.0.refname_to_id(&format!("refs/heads/{}", branch))?,
+            &tagname,
+            &self.render_tag_message(kind, name, branch, opts)?,
+        )?;
+
+        Ok(tagname)
+    }
+
+    // the tip of a branch name or full ref name
+    fn rev_head(&self, from: &str) -> Result<Oid> {
+        if from.starts_with("refs/") {
+            Ok(self.repo.0.find_reference(from)?.peel_to_commit()?.id())
+        } else {
+            self.branch_head(from)
+        }
+    }
+
+    // checkout `into` and merge `from`, a branch or a full ref name, into it
+    fn merge_into(&self, from: &str, into: &str, msg: Option<&str>) -> Result<Merge> {
+        let msg = match msg {
+            Some(m) if !m.is_empty() => m.to_string(),
+            _ => match from.strip_prefix("refs/tags/") {
+                Some(tag) => format!("Merge tag '{}' into {}", tag, into),
+                None => format!("Merge branch '{}' into {}", from, into),
+            },
+        };
+
+        self.repo.checkout(into)?;
+        let from_ref = if from.starts_with("refs/") {
+            self.repo.0.find_reference(from)?
+        } else {
+            self.repo.0.find_reference(&format!("refs/heads/{}", from))?
+        };
+        let kind = self.repo.merge(
+            into,
+            self.repo.0.reference_to_annotated_commit(&from_ref)?,
+            &msg,
+        )?;
+
+        if kind == MergeKind::Conflict {
+            return Err(Error::MergeConflict {
+                from: from.to_string(),
+                into: into.to_string(),
+            });
+        }
+
+        Ok(Merge {
+            from: from.to_string(),
+            into: into.to_string(),
+            kind,
+            head: self.repo.0.refname_to_id(&format!("refs/heads/{}", into))?,
+        })
+    }
+}
+
+// where start remembers an explicit base branch
+fn base_key(branch: &str) -> String {
+    format!("branch.{}.gitflowBase", branch)
+}
+
+// where git-flow AVH remembers the base of every branch it starts
+fn avh_base_key(branch: &str) -> String {
+    format!("gitflow.branch.{}.base", branch)
+}
+
+// keep the error of a step that goes on anyway
+fn attempt<T, E: Display>(errors: &mut Vec<String>, result: std::result::Result<T, E>) {
+    if let Err(e) = result {
+        errors.push(e.to_string());
+    }
+}
+
+// the branches merged into, once each
+fn merge_targets(merges: &[(String, String)]) -> Vec<&str> {
+    let mut targets = Vec::new();
+    for (_, into) in merges {
+        if !targets.contains(&into.as_str()) {
+            targets.push(into.as_str());
+        }
+    }
+
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{
+            self,
+            remove_dir_all,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+    };
+    use git2::Repository;
+    use crate::{
+        gf::GfBranch,
+        gitc::MergeKind,
+        utils::get_branch_name,
+    };
+    use crate::error::Error;
+    use super::{
+        Flow,
+        InitOptions,
+        StartOptions,
+        FinishOptions,
+        DeleteOptions,
+        Step,
+        PickKind,
+    };
+
+    fn set_test_flow(name: &str) -> Flow {
+        let p: PathBuf = env::temp_dir().join(name);
+        let _ = remove_dir_all(&p);
+        let repo = Repository::init(&p).unwrap();
+        {
+            let mut conf = repo.config().unwrap();
+            conf.set_str("user.name", "git-flow").unwrap();
+            conf.set_str("user.email", "git-flow@localhost").unwrap();
+        }
+        let flow = Flow::open(&p).unwrap();
+        flow.init(&InitOptions::default()).unwrap();
+        flow
+    }
+
+    #[test]
+    fn test_open_missing() {
+        assert!(Flow::open(env::temp_dir().join("gf-flow-missing/nothing")).is_err());
+    }
+
+    #[test]
+    fn test_feature() {
+        let flow = set_test_flow("gf-flow-feature");
+        flow.repo().checkout("develop").unwrap();
+
+        let started = flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        assert_eq!(started.branch, "feature/x");
+        assert_eq!(started.base, "develop");
+        assert_eq!(get_branch_name(&flow.repo().0).unwrap(), "feature/x");
+        let listed = flow.list(&GfBranch::Feature).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "x");
+        assert!(listed[0].current);
+
+        flow.repo().commit("add x").unwrap();
+        let listed = flow.list(&GfBranch::Feature).unwrap();
+        assert_eq!((listed[0].ahead, listed[0].behind), (1, 0));
+
+        let finished = flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges.len(), 1);
+        assert_eq!(finished.merges[0].into, "develop");
+        assert_eq!(finished.merges[0].kind, MergeKind::FastForward);
+        assert_eq!(finished.deleted, vec!["feature/x".to_string()]);
+        assert!(flow.list(&GfBranch::Feature).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let flow = set_test_flow("gf-flow-errors");
+
+        match flow.finish(&GfBranch::Feature, "nothing", &FinishOptions::default()) {
+            Err(Error::BranchNotFound(b)) => assert_eq!(b, "feature/nothing"),
+            _ => panic!("finishing a missing branch should fail"),
+        }
+
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        match flow.start(&GfBranch::Feature, "x", &StartOptions::default()) {
+            Err(e @ Error::BranchExists(_)) => assert_eq!(e.exit_code(), 4),
+            _ => panic!("starting an existing branch should fail"),
+        }
+
+        flow.repo().0.config().unwrap().remove("gitflow.prefix.release").unwrap();
+        match flow.start(&GfBranch::Release, "y", &StartOptions::default()) {
+            Err(Error::NotInitialized(key)) => assert_eq!(key, "gitflow.prefix.release"),
+            _ => panic!("a missing prefix means git-flow is not initialized"),
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let flow = set_test_flow("gf-flow-status");
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        flow.start(&GfBranch::Release, "1.0", &StartOptions { no_checkout: true, ..Default::default() }).unwrap();
+        flow.start(&GfBranch::Release, "1.1", &StartOptions { no_checkout: true, ..Default::default() }).unwrap();
+
+        let status = flow.status().unwrap();
+        assert_eq!(status.current.as_deref(), Some("feature/x"));
+        assert_eq!(status.current_type.as_deref(), Some("feature"));
+        assert_eq!(status.branches["release"].len(), 2);
+        assert!(status.branches["hotfix"].is_empty());
+        assert_eq!(status.in_progress, None);
+        assert_eq!(status.warnings, vec!["2 release branches are open: release/1.0, release/1.1".to_string()]);
+    }
+
+    #[test]
+    fn test_publish_fork() {
+        let origin = env::temp_dir().join("gf-flow-fork-origin");
+        let _ = remove_dir_all(&origin);
+        Repository::init_bare(&origin).unwrap();
+
+        let mut flow = set_test_flow("gf-flow-fork");
+        flow.repo().0.remote("fork", origin.to_str().unwrap()).unwrap();
+        flow.repo().config("gitflow.origin", "fork").unwrap();
+        flow.repo().config("gitflow.upstream", "nowhere").unwrap();
+        assert_eq!(flow.origin().unwrap(), "fork");
+        assert_eq!(flow.upstream().unwrap(), "nowhere");
+
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        let published = flow.publish(&GfBranch::Feature, "x").unwrap();
+        assert_eq!(published.remote, "fork");
+        assert_eq!(
+            Repository::open_bare(&origin).unwrap().refname_to_id("refs/heads/feature/x").unwrap(),
+            published.head
+        );
+        assert_eq!(flow.repo().get_config("branch.feature/x.remote").unwrap(), "fork");
+
+        flow.set_remote(Some("other".to_string()));
+        assert_eq!(flow.origin().unwrap(), "other");
+        assert_eq!(flow.upstream().unwrap(), "other");
+    }
+
+    #[test]
+    fn test_custom_type() {
+        let flow = set_test_flow("gf-flow-custom");
+        flow.repo().config("gitflow.type.experiment.prefix", "exp/").unwrap();
+        flow.repo().config("gitflow.type.experiment.base", "develop").unwrap();
+        flow.repo().config("gitflow.type.experiment.finishInto", "master").unwrap();
+        flow.repo().config("gitflow.type.experiment.tag", "true").unwrap();
+        flow.repo().config("gitflow.type.docs.base", "master").unwrap();
+
+        let types = flow.branch_types().unwrap();
+        let experiment = GfBranch::Custom("experiment".to_string());
+        let docs = GfBranch::Custom("docs".to_string());
+        assert!(types.contains(&experiment));
+        assert!(types.contains(&docs));
+        assert_eq!(flow.prefix(&docs).unwrap(), "docs/");
+        assert_eq!(flow.base_of(&docs).unwrap(), "master");
+
+        flow.start(&experiment, "a", &StartOptions::default()).unwrap();
+        flow.repo().commit("try a").unwrap();
+        assert_eq!(flow.parse_branch("exp/a").unwrap(), Some((experiment.clone(), "a".to_string())));
+
+        // no branch to merge into is not a license to drop it
+        flow.repo().config("gitflow.type.experiment.finishInto", ",").unwrap();
+        assert!(flow.finish(&experiment, "a", &FinishOptions::default()).is_err());
+        assert!(flow.merge_message(&experiment, "a", &FinishOptions::default()).is_err());
+        assert!(flow.branch_head("exp/a").is_ok());
+        flow.repo().config("gitflow.type.experiment.finishInto", "master").unwrap();
+
+        let finished = flow.finish(&experiment, "a", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges.len(), 1);
+        assert_eq!(finished.merges[0].into, "master");
+        assert_eq!(finished.tag.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_start_base() {
+        let flow = set_test_flow("gf-flow-start-base");
+        flow.repo().checkout("develop").unwrap();
+        flow.repo().commit("develop only").unwrap();
+
+        // the configured base, not HEAD
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        let hotfix = flow.start(&GfBranch::Hotfix, "1.0.1", &StartOptions::default()).unwrap();
+        assert_eq!(hotfix.base, "master");
+        assert_eq!(hotfix.head, flow.repo().0.refname_to_id("refs/heads/master").unwrap());
+
+        flow.repo().config("gitflow.feature.start.base", "master").unwrap();
+        let started = flow.start(&GfBranch::Feature, "y", &StartOptions::default()).unwrap();
+        assert_eq!(started.base, "master");
+
+        flow.repo().tag(started.head, "v1.0", "v1.0").unwrap();
+        let opts = StartOptions {
+            base: Some("v1.0".to_string()),
+            ..Default::default()
+        };
+        let started = flow.start(&GfBranch::Support, "1.x", &opts).unwrap();
+        assert_eq!(started.head, flow.repo().0.refname_to_id("refs/heads/master").unwrap());
+
+        let opts = StartOptions {
+            base: Some("nowhere".to_string()),
+            ..Default::default()
+        };
+        match flow.start(&GfBranch::Feature, "z", &opts) {
+            Err(Error::InvalidBase(base)) => assert_eq!(base, "nowhere"),
+            _ => panic!("an unknown base should be refused"),
+        }
+        assert!(flow.repo().0.find_branch("feature/z", git2::BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_naming() {
+        let flow = set_test_flow("gf-flow-naming");
+
+        for bad in &["with space", "a..b", "x.lock", "-x", "x/", "x@{1}", ".hidden"] {
+            match flow.start(&GfBranch::Feature, bad, &StartOptions::default()) {
+                Err(e @ Error::InvalidName { .. }) => assert_eq!(e.exit_code(), 10),
+                _ => panic!("{} is not a valid name", bad),
+            }
+        }
+
+        flow.repo().config("gitflow.feature.policy", r"^[A-Z]+-\d+-[a-z0-9-]+$").unwrap();
+        flow.repo().config("gitflow.release.policy", "semver").unwrap();
+        assert!(flow.validate_name(&GfBranch::Feature, "add-login").is_err());
+        assert!(flow.validate_name(&GfBranch::Feature, "GF-12-add-login").is_ok());
+        assert!(flow.validate_name(&GfBranch::Release, "1.2").is_err());
+        assert!(flow.validate_name(&GfBranch::Release, "1.2.0-rc.1").is_ok());
+
+        flow.start(&GfBranch::Feature, "GF-12-add-login", &StartOptions::default()).unwrap();
+        assert!(flow.rename(&GfBranch::Feature, "GF-12-add-login", "login").is_err());
+        let renamed = flow.rename(&GfBranch::Feature, "GF-12-add-login", "GF-12-login").unwrap();
+        assert_eq!(renamed.to, "feature/GF-12-login");
+        assert_eq!(get_branch_name(&flow.repo().0).unwrap(), "feature/GF-12-login");
+    }
+
+    #[test]
+    fn test_delete() {
+        let flow = set_test_flow("gf-flow-delete");
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        flow.repo().commit("unmerged work").unwrap();
+        flow.repo().checkout("develop").unwrap();
+
+        match flow.delete(&GfBranch::Feature, "x", &DeleteOptions::default()) {
+            Err(e @ Error::NotMerged { .. }) => assert_eq!(e.exit_code(), 13),
+            _ => panic!("unmerged branches are kept"),
+        }
+        // git won't delete the current branch, which leaves no archive
+        flow.repo().checkout("feature/x").unwrap();
+        assert!(flow.delete(&GfBranch::Feature, "x", &DeleteOptions { force: true, ..Default::default() }).is_err());
+        assert!(flow.archives().unwrap().is_empty());
+        flow.repo().checkout("develop").unwrap();
+
+        let deleted = flow.delete(&GfBranch::Feature, "x", &DeleteOptions { force: true, ..Default::default() }).unwrap();
+        assert_eq!(deleted.branch, "feature/x");
+        assert!(flow.list(&GfBranch::Feature).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_release() {
+        let flow = set_test_flow("gf-flow-release");
+        flow.repo().checkout("develop").unwrap();
+
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        flow.repo().commit("bump version").unwrap();
+        let finished = flow.finish(&GfBranch::Release, "1.0", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.tag.as_deref(), Some("1.0"));
+        assert_eq!(finished.merges.len(), 2);
+        assert!(flow.repo().0.find_reference("refs/tags/1.0").is_ok());
+        assert_eq!(
+            flow.repo().0.refname_to_id("refs/heads/master").unwrap(),
+            flow.repo().0.refname_to_id("refs/heads/develop").unwrap()
+        );
+
+        flow.start(&GfBranch::Release, "1.1", &StartOptions::default()).unwrap();
+        flow.repo().commit("bump version").unwrap();
+        let develop = flow.repo().0.refname_to_id("refs/heads/develop").unwrap();
+        let opts = FinishOptions {
+            no_backmerge: true,
+            ..Default::default()
+        };
+        let finished = flow.finish(&GfBranch::Release, "1.1", &opts).unwrap();
+        assert_eq!(finished.merges.len(), 1);
+        assert_eq!(flow.repo().0.refname_to_id("refs/heads/develop").unwrap(), develop);
+
+        flow.repo().config("gitflow.release.finish.backmerge", "branch").unwrap();
+        flow.start(&GfBranch::Release, "1.2", &StartOptions::default()).unwrap();
+        flow.repo().commit("bump version").unwrap();
+        let finished = flow.finish(&GfBranch::Release, "1.2", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges[1].from, "release/1.2");
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let flow = set_test_flow("gf-flow-dry-run");
+        flow.repo().checkout("master").unwrap();
+        flow.repo().commit("hotfix on master").unwrap();
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        flow.repo().commit("bump version").unwrap();
+
+        let refs = |flow: &Flow| ["master", "develop", "release/1.0"].iter()
+            .map(|b| flow.repo().0.refname_to_id(&format!("refs/heads/{}", b)).unwrap())
+            .collect::<Vec<_>>();
+        let before = refs(&flow);
+        let objects = |flow: &Flow| {
+            let mut n = 0;
+            flow.repo().0.odb().unwrap().foreach(|_| { n += 1; true }).unwrap();
+            n
+        };
+        let stored = objects(&flow);
+
+        let plan = flow.plan_finish(&GfBranch::Release, "1.0", &FinishOptions::default()).unwrap();
+        let kinds = plan.steps.iter()
+            .filter_map(|s| match s {
+                Step::Merge { kind, .. } => Some(*kind),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![MergeKind::Normal, MergeKind::FastForward]);
+        assert!(matches!(&plan.steps[1], Step::Tag { name, branch } if name == "1.0" && branch == "master"));
+        assert!(matches!(plan.steps.last(), Some(Step::Delete { branch }) if branch == "release/1.0"));
+
+        assert!(flow.plan_start(&GfBranch::Release, "1.0", &StartOptions::default()).is_err());
+        assert!(flow.plan_delete(&GfBranch::Release, "1.0", &DeleteOptions::default()).is_err());
+        assert_eq!(refs(&flow), before);
+        assert_eq!(objects(&flow), stored);
+        assert!(flow.repo().0.find_reference("refs/tags/1.0").is_err());
+        assert_eq!(get_branch_name(&flow.repo().0).unwrap(), "release/1.0");
+    }
+
+    // write `content` to `file` and commit it on the current branch
+    fn commit_file(flow: &Flow, file: &str, content: &str) {
+        let workdir = flow.repo().get_workdir().unwrap();
+        fs::write(workdir.join(file), content).unwrap();
+        let mut index = flow.repo().0.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        flow.repo().commit(&format!("{} {}", file, content)).unwrap();
+    }
+
+    #[test]
+    fn test_finish_rollback() {
+        let flow = set_test_flow("gf-flow-rollback");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        commit_file(&flow, "VERSION", "0.9");
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        commit_file(&flow, "VERSION", "1.0");
+        flow.repo().checkout("develop").unwrap();
+        commit_file(&flow, "VERSION", "1.1-dev");
+
+        let heads = |names: &[&str]| names.iter()
+            .map(|b| repo.refname_to_id(&format!("refs/heads/{}", b)).unwrap())
+            .collect::<Vec<_>>();
+        let before = heads(&["master", "develop", "release/1.0"]);
+
+        // master takes the release, tagged, then develop conflicts
+        match flow.finish(&GfBranch::Release, "1.0", &FinishOptions::default()) {
+            Err(Error::MergeConflict { from, into }) => {
+                assert_eq!(from, "refs/tags/1.0");
+                assert_eq!(into, "develop");
+            }
+            r => panic!("merging the tag back into develop should conflict: {:?}", r),
+        }
+        assert_eq!(heads(&["master", "develop", "release/1.0"]), before);
+        assert!(repo.find_reference("refs/tags/1.0").is_err());
+        assert_eq!(get_branch_name(repo).unwrap(), "develop");
+        assert!(flow.repo().is_clean().unwrap());
+        assert!(!repo.index().unwrap().has_conflicts());
+    }
+
+    #[test]
+    fn test_undo() {
+        let flow = set_test_flow("gf-flow-undo");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        let develop = repo.refname_to_id("refs/heads/develop").unwrap();
+
+        let opts = StartOptions {
+            base: Some("develop".to_string()),
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Feature, "x", &opts).unwrap();
+        flow.repo().commit("work").unwrap();
+        let x = repo.refname_to_id("refs/heads/feature/x").unwrap();
+        flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).unwrap();
+
+        let log = flow.log().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1].command, "feature finish x");
+        assert_eq!(log[1].deleted, vec!["feature/x"]);
+        assert!(flow.stored_base("feature/x").is_none());
+
+        // the wrong feature was finished
+        let undo = flow.undo(false).unwrap();
+        assert_eq!(undo.undone.id, 2);
+        assert_eq!(repo.refname_to_id("refs/heads/develop").unwrap(), develop);
+        assert_eq!(repo.refname_to_id("refs/heads/feature/x").unwrap(), x);
+        assert_eq!(get_branch_name(repo).unwrap(), "feature/x");
+        // with the base it was started from
+        assert_eq!(flow.stored_base("feature/x").as_deref(), Some("develop"));
+
+        // then the start, the commit made on the branch since needs -f
+        assert!(flow.undo(false).is_err());
+        flow.undo(true).unwrap();
+        assert!(repo.find_branch("feature/x", git2::BranchType::Local).is_err());
+        assert_eq!(get_branch_name(repo).unwrap(), "develop");
+        assert!(flow.undo(false).is_err());
+    }
+
+    #[test]
+    fn test_archive() {
+        let flow = set_test_flow("gf-flow-archive");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        flow.repo().commit("work").unwrap();
+        let x = repo.refname_to_id("refs/heads/feature/x").unwrap();
+        flow.repo().checkout("develop").unwrap();
+        flow.delete(&GfBranch::Feature, "x", &DeleteOptions { force: true, ..Default::default() }).unwrap();
+
+        assert_eq!(repo.refname_to_id("refs/gitflow/archive/feature/x").unwrap(), x);
+        let restored = flow.restore(&GfBranch::Feature, "x").unwrap();
+        assert_eq!(restored.head, x);
+        assert!(flow.restore(&GfBranch::Feature, "x").is_err());
+        assert!(flow.restore(&GfBranch::Feature, "y").is_err());
+
+        flow.repo().config("gitflow.archive", "tags").unwrap();
+        flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).unwrap();
+        assert_eq!(flow.archives().unwrap().len(), 2);
+        assert!(repo.find_reference("refs/tags/archive/feature/x").is_ok());
+
+        assert!(flow.prune_archives(3600).unwrap().is_empty());
+        assert_eq!(flow.prune_archives(-3600).unwrap().len(), 2);
+        assert!(flow.archives().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_merge_message() {
+        let flow = set_test_flow("gf-flow-message");
+        let repo = &flow.repo().0;
+        let message = |branch: &str| repo.find_reference(&format!("refs/heads/{}", branch)).unwrap()
+            .peel_to_commit().unwrap()
+            .message().unwrap()
+            .to_string();
+        flow.repo().checkout("develop").unwrap();
+
+        flow.start(&GfBranch::Feature, "GF-1", &StartOptions::default()).unwrap();
+        commit_file(&flow, "a", "1");
+        flow.repo().checkout("develop").unwrap();
+        commit_file(&flow, "b", "1");
+        assert_eq!(
+            flow.merge_message(&GfBranch::Feature, "GF-1", &FinishOptions::default()).unwrap(),
+            "Merge branch 'feature/GF-1' into develop"
+        );
+        flow.repo().config("gitflow.feature.merge.message", "{type} {name}: {branch} into {base}").unwrap();
+        flow.finish(&GfBranch::Feature, "GF-1", &FinishOptions::default()).unwrap();
+        assert_eq!(message("develop"), "feature GF-1: feature/GF-1 into develop");
+
+        flow.repo().checkout("master").unwrap();
+        commit_file(&flow, "c", "1");
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        let opts = FinishOptions {
+            message: Some("Release {version}".to_string()),
+            tag: Some("v1.0".to_string()),
+            ..Default::default()
+        };
+        flow.finish(&GfBranch::Release, "1.0", &opts).unwrap();
+        assert_eq!(message("master"), "Release v1.0");
+    }
+
+    #[test]
+    fn test_hotfix_into_release() {
+        let flow = set_test_flow("gf-flow-hotfix-release");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        commit_file(&flow, "VERSION", "1.0");
+        let develop = repo.refname_to_id("refs/heads/develop").unwrap();
+
+        flow.start(&GfBranch::Hotfix, "0.9.1", &StartOptions::default()).unwrap();
+        commit_file(&flow, "fix", "1");
+        let hotfix = repo.refname_to_id("refs/heads/hotfix/0.9.1").unwrap();
+        let finished = flow.finish(&GfBranch::Hotfix, "0.9.1", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges[1].into, "release/1.0");
+        let release = repo.refname_to_id("refs/heads/release/1.0").unwrap();
+        assert!(repo.graph_descendant_of(release, hotfix).unwrap());
+        assert_eq!(repo.refname_to_id("refs/heads/develop").unwrap(), develop);
+
+        flow.start(&GfBranch::Hotfix, "0.9.2", &StartOptions::default()).unwrap();
+        commit_file(&flow, "fix", "2");
+        let opts = FinishOptions {
+            no_release: true,
+            ..Default::default()
+        };
+        let finished = flow.finish(&GfBranch::Hotfix, "0.9.2", &opts).unwrap();
+        assert_eq!(finished.merges[1].into, "develop");
+    }
+
+    #[test]
+    fn test_support_hotfix() {
+        let flow = set_test_flow("gf-flow-support");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        commit_file(&flow, "VERSION", "1.0");
+        flow.finish(&GfBranch::Release, "1.0", &FinishOptions::default()).unwrap();
+        let master = repo.refname_to_id("refs/heads/master").unwrap();
+
+        let support = StartOptions {
+            base: Some("1.0".to_string()),
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Support, "1.x", &support).unwrap();
+        let hotfix = StartOptions {
+            base: Some("support/1.x".to_string()),
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Hotfix, "1.0.1", &hotfix).unwrap();
+        assert_eq!(flow.stored_base("hotfix/1.0.1").as_deref(), Some("support/1.x"));
+        commit_file(&flow, "VERSION", "1.0.1");
+
+        let finished = flow.finish(&GfBranch::Hotfix, "1.0.1", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges.len(), 1);
+        assert_eq!(finished.merges[0].into, "support/1.x");
+        assert_eq!(
+            repo.find_reference("refs/tags/1.0.1").unwrap().peel_to_commit().unwrap().id(),
+            repo.refname_to_id("refs/heads/support/1.x").unwrap()
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/master").unwrap(), master);
+    }
+
+    #[test]
+    fn test_backport() {
+        let flow = set_test_flow("gf-flow-backport");
+        let repo = &flow.repo().0;
+        let master = StartOptions {
+            base: Some("master".to_string()),
+            no_checkout: true,
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Support, "1.x", &master).unwrap();
+        flow.start(&GfBranch::Support, "2.x", &master).unwrap();
+        flow.repo().checkout("master").unwrap();
+        commit_file(&flow, "a.txt", "fix");
+        let fix = repo.refname_to_id("refs/heads/master").unwrap();
+
+        let done = flow.backport(&fix.to_string(), &["1.x".to_string()]).unwrap();
+        assert_eq!(done.lines.len(), 1);
+        assert_eq!(done.lines[0].picked.len(), 1);
+        let picked = repo.find_commit(repo.refname_to_id("refs/heads/support/1.x").unwrap()).unwrap();
+        assert!(picked.message().unwrap().ends_with(&format!("(cherry picked from commit {})\n", fix)));
+
+        // 1.x has it already, by patch id
+        let done = flow.backport(&fix.to_string(), &[]).unwrap();
+        assert_eq!(done.lines[0].applied, vec![fix.to_string()]);
+        assert_eq!(done.lines[1].picked.len(), 1);
+
+        flow.repo().checkout("support/2.x").unwrap();
+        commit_file(&flow, "a.txt", "other");
+        flow.repo().checkout("master").unwrap();
+        commit_file(&flow, "a.txt", "fix2");
+        let fix2 = repo.refname_to_id("refs/heads/master").unwrap().to_string();
+
+        let plan = flow.plan_backport(&fix2, &[]).unwrap();
+        assert!(matches!(plan.steps.last(), Some(Step::Pick { kind: PickKind::Conflict, .. })));
+        let stopped = flow.backport(&fix2, &[]).unwrap();
+        assert!(stopped.stopped);
+        assert_eq!(stopped.lines[1].conflict.as_deref(), Some(fix2.as_str()));
+        assert_eq!(get_branch_name(repo).unwrap(), "support/2.x");
+        assert_eq!(flow.status().unwrap().in_progress.as_deref(), Some("backport"));
+        assert!(flow.backport(&fix2, &[]).is_err());
+
+        // giving up is journaled like the rest, then the same again
+        flow.backport_abort().unwrap();
+        assert_eq!(get_branch_name(repo).unwrap(), "master");
+        assert_eq!(flow.log().unwrap().last().unwrap().command, "backport --abort");
+        assert!(flow.backport(&fix2, &[]).unwrap().stopped);
+
+        assert!(flow.backport_continue().is_err());
+        let workdir = flow.repo().get_workdir().unwrap();
+        fs::write(workdir.join("a.txt"), "fix2").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let done = flow.backport_continue().unwrap();
+        assert!(!done.stopped);
+        assert_eq!(done.lines[1].picked.len(), 1);
+        assert_eq!(get_branch_name(repo).unwrap(), "master");
+        assert!(flow.backport_abort().is_err());
+    }
+
+    #[test]
+    fn test_backport_fast_forward() {
+        let flow = set_test_flow("gf-flow-backport-ff");
+        let repo = &flow.repo().0;
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        commit_file(&flow, "VERSION", "1.0");
+        flow.finish(&GfBranch::Release, "1.0", &FinishOptions::default()).unwrap();
+        let support = StartOptions {
+            base: Some("1.0".to_string()),
+            no_checkout: true,
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Support, "1.x", &support).unwrap();
+
+        // master has not moved since 1.0, finish fast-forwards it
+        flow.start(&GfBranch::Hotfix, "1.0.1", &StartOptions::default()).unwrap();
+        commit_file(&flow, "a.txt", "fix");
+        commit_file(&flow, "b.txt", "fix");
+        flow.finish(&GfBranch::Hotfix, "1.0.1", &FinishOptions::default()).unwrap();
+        let tagged = repo.revparse_single("1.0.1").unwrap().peel_to_commit().unwrap();
+        assert_eq!(tagged.parent_count(), 1);
+
+        let done = flow.backport("1.0.1", &["1.x".to_string()]).unwrap();
+        assert_eq!(done.lines[0].picked.len(), 2);
+        // a commit alone is just itself
+        assert_eq!(flow.backport_commits(&tagged.id().to_string()).unwrap(), vec![tagged.id()]);
+        assert!(flow.backport_commits("1.0").is_err());
+    }
+
+    #[test]
+    fn test_bugfix_on_release() {
+        let flow = set_test_flow("gf-flow-bugfix-release");
+        let repo = &flow.repo().0;
+        flow.start(&GfBranch::Release, "1.2", &StartOptions::default()).unwrap();
+        let on_release = StartOptions {
+            base: Some("release/1.2".to_string()),
+            ..Default::default()
+        };
+        flow.start(&GfBranch::Bugfix, "x", &on_release).unwrap();
+        flow.start(&GfBranch::Bugfix, "y", &on_release).unwrap();
+        assert_eq!(flow.list(&GfBranch::Bugfix).unwrap()[0].base, "release/1.2");
+        let develop = repo.refname_to_id("refs/heads/develop").unwrap();
+
+        flow.repo().checkout("bugfix/x").unwrap();
+        commit_file(&flow, "fix.txt", "x");
+        let finished = flow.finish(&GfBranch::Bugfix, "x", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.merges[0].into, "release/1.2");
+        assert_eq!(repo.refname_to_id("refs/heads/develop").unwrap(), develop);
+
+        // once the release is finished, develop has it
+        flow.finish(&GfBranch::Release, "1.2", &FinishOptions::default()).unwrap();
+        assert_eq!(flow.list(&GfBranch::Bugfix).unwrap()[0].base, "develop");
+        let plan = flow.plan_finish(&GfBranch::Bugfix, "y", &FinishOptions::default()).unwrap();
+        assert!(matches!(&plan.steps[0], Step::Merge { into, .. } if into == "develop"));
+    }
+
+    #[test]
+    fn test_current_branch() {
+        let flow = set_test_flow("gf-flow-current");
+        let repo = &flow.repo().0;
+        flow.repo().checkout("develop").unwrap();
+        assert!(flow.current().is_err());
+        flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap();
+        assert_eq!(flow.current().unwrap(), (GfBranch::Feature, "x".to_string()));
+        commit_file(&flow, "x.txt", "x");
+
+        let diff = flow.diff(&GfBranch::Feature, "x").unwrap();
+        assert_eq!(diff.base, "develop");
+        assert_eq!(diff.files, vec!["x.txt"]);
+        assert!(diff.patch.contains("+x"));
+
+        flow.repo().checkout("develop").unwrap();
+        commit_file(&flow, "d.txt", "d");
+        let develop = repo.refname_to_id("refs/heads/develop").unwrap();
+        let rebased = flow.rebase(&GfBranch::Feature, "x").unwrap();
+        assert!(repo.graph_descendant_of(rebased.head, develop).unwrap());
+        assert_eq!(flow.diff(&GfBranch::Feature, "x").unwrap().files, vec!["x.txt"]);
+    }
+
+    #[test]
+    fn test_create_in_subdirectory() {
+        let flow = set_test_flow("gf-flow-create");
+        let top = flow.repo().get_workdir().unwrap().to_path_buf();
+        let sub = top.join("a/b");
+        fs::create_dir_all(&sub).unwrap();
+
+        // the repository above is used instead of a nested new one
+        let found = Flow::create(&sub).unwrap();
+        assert_eq!(found.repo().get_workdir().unwrap(), top.as_path());
+        assert!(!sub.join(".git").exists());
+        assert!(Flow::open(&sub).is_err());
+    }
+
+    #[test]
+    fn test_bare() {
+        let p: PathBuf = env::temp_dir().join("gf-flow-bare");
+        let _ = remove_dir_all(&p);
+        let repo = Repository::init_bare(&p).unwrap();
+        {
+            let mut conf = repo.config().unwrap();
+            conf.set_str("user.name", "git-flow").unwrap();
+            conf.set_str("user.email", "git-flow@localhost").unwrap();
+        }
+        let flow = Flow::open(&p).unwrap();
+        flow.init(&InitOptions::default()).unwrap();
+        assert_eq!(flow.config().unwrap().hooks, p.join("hooks").to_string_lossy());
+
+        // nothing is left behind by what needs a working tree
+        let err = flow.start(&GfBranch::Feature, "x", &StartOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::NoWorkTree(_)));
+        assert!(repo.find_reference("refs/heads/feature/x").is_err());
+
+        flow.repo().config("core.hooksPath", "/srv/hooks").unwrap();
+        assert_eq!(flow.default_hooks_dir().unwrap(), "/srv/hooks");
+    }
+
+    #[test]
+    fn test_worktree() {
+        let flow = set_test_flow("gf-flow-worktree");
+        let path = env::temp_dir().join("gf-flow-worktree-x");
+        let _ = remove_dir_all(&path);
+        flow.repo().checkout("develop").unwrap();
+        assert_eq!(flow.default_worktree("a/b").unwrap(), env::temp_dir().join("gf-flow-worktree-a-b"));
+
+        let opts = StartOptions {
+            worktree: true,
+            worktree_path: Some(path.clone()),
+            ..Default::default()
+        };
+        let started = flow.start(&GfBranch::Feature, "x", &opts).unwrap();
+        assert!(started.worktree.is_some());
+        assert_eq!(get_branch_name(&flow.repo().0).unwrap(), "develop");
+        let checkout = flow.checkout(&GfBranch::Feature, "x").unwrap();
+        assert_eq!(checkout.worktree, started.worktree);
+
+        // work happens over there, leftovers keep finish from removing it
+        let tree = Flow::open(&path).unwrap();
+        commit_file(&tree, "x.txt", "x");
+        fs::write(path.join("tmp.txt"), "tmp").unwrap();
+        assert!(flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).is_err());
+        assert!(tree.finish(&GfBranch::Feature, "x", &FinishOptions::default()).is_err());
+
+        // a late failure keeps the worktree and the branch
+        fs::remove_file(path.join("tmp.txt")).unwrap();
+        let develop = flow.branch_head("develop").unwrap();
+        flow.repo().config("gitflow.archive", "bogus").unwrap();
+        assert!(flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).is_err());
+        assert!(path.join("x.txt").exists());
+        assert_eq!(get_branch_name(&tree.repo().0).unwrap(), "feature/x");
+        assert_eq!(flow.branch_head("develop").unwrap(), develop);
+
+        flow.repo().config("gitflow.archive", "refs").unwrap();
+        let finished = flow.finish(&GfBranch::Feature, "x", &FinishOptions::default()).unwrap();
+        assert_eq!(finished.worktree, started.worktree);
+        assert!(!path.exists());
+        assert!(flow.repo().get_workdir().unwrap().join("x.txt").exists());
+
+        // changes in a worktree take more than deleting an unmerged branch
+        flow.start(&GfBranch::Feature, "y", &opts).unwrap();
+        fs::write(path.join("tmp.txt"), "tmp").unwrap();
+        let force = DeleteOptions {
+            force: true,
+            ..Default::default()
+        };
+        assert!(flow.delete(&GfBranch::Feature, "y", &force).is_err());
+        assert!(path.join("tmp.txt").exists());
+        let force = DeleteOptions {
+            force_worktree: true,
+            ..force
+        };
+        let deleted = flow.delete(&GfBranch::Feature, "y", &force).unwrap();
+        assert!(deleted.worktree.is_some());
+        assert!(!path.exists());
+        assert!(flow.repo().0.find_branch("feature/y", git2::BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_shared_config() {
+        let flow = set_test_flow("gf-flow-shared");
+        let workdir = flow.repo().get_workdir().unwrap().to_path_buf();
+        fs::write(workdir.join(".gitflow"), "[gitflow \"prefix\"]\n\tfeature = feat/\n\
+                  [gitflow \"path\"]\n\thooks = /srv/hooks\n[core]\n\teditor = rm -rf\n").unwrap();
+        // init already wrote its own prefix, git config comes first
+        assert_eq!(flow.prefix(&GfBranch::Feature).unwrap(), "feature/");
+
+        flow.repo().0.config().unwrap().remove("gitflow.prefix.feature").unwrap();
+        assert_eq!(flow.prefix(&GfBranch::Feature).unwrap(), "feat/");
+        assert_ne!(flow.config().unwrap().hooks, "/srv/hooks");
+        assert_eq!(flow.repo().get_shared("core.editor").unwrap(), None);
+
+        // init takes it over without writing it down
+        let opts = flow.init_options().unwrap();
+        assert_eq!(opts.feature, "feat/");
+        flow.init(&opts).unwrap();
+        assert!(flow.repo().0.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap()
+                .get_string("gitflow.prefix.feature").is_err());
+        assert_eq!(flow.prefix(&GfBranch::Feature).unwrap(), "feat/");
+    }
+
+    #[test]
+    fn test_avh_config() {
+        let flow = set_test_flow("gf-flow-avh");
+        let repo = &flow.repo().0;
+        {
+            // what git-flow AVH 1.8 leaves behind
+            let mut conf = repo.config().unwrap();
+            conf.remove("gitflow.prefix.bugfix").unwrap();
+            conf.remove("gitflow.path.hooks").unwrap();
+            conf.set_bool("gitflow.feature.finish.keep", true).unwrap();
+            conf.set_bool("gitflow.feature.finish.squash", true).unwrap();
+            conf.set_bool("gitflow.multi-hotfix", false).unwrap();
+        }
+        let config = flow.config().unwrap();
+        assert_eq!(config.prefixes["bugfix"], "bugfix/");
+        assert_eq!(config.unsupported, vec!["gitflow.feature.finish.squash"]);
+        assert!(flow.finish_options(&GfBranch::Feature).keep);
+        assert!(!flow.finish_options(&GfBranch::Bugfix).keep);
+
+        // the base it remembers counts like ours
+        flow.repo().checkout("develop").unwrap();
+        commit_file(&flow, "d.txt", "d");
+        flow.start(&GfBranch::Release, "1.0", &StartOptions::default()).unwrap();
+        repo.branch("feature/x", &repo.head().unwrap().peel_to_commit().unwrap(), false).unwrap();
+        flow.repo().config("gitflow.branch.feature/x.base", "release/1.0").unwrap();
+        assert_eq!(flow.list(&GfBranch::Feature).unwrap()[0].base, "release/1.0");
+        flow.delete(&GfBranch::Feature, "x", &DeleteOptions { force: true, ..Default::default() }).unwrap();
+        assert!(flow.stored_base("feature/x").is_none());
+
+        flow.start(&GfBranch::Hotfix, "1.0.1", &StartOptions::default()).unwrap();
+        assert!(flow.start(&GfBranch::Hotfix, "1.0.2", &StartOptions::default()).is_err());
+        commit_file(&flow, "h.txt", "h");
+        let hotfix = flow.branch_head("hotfix/1.0.1").unwrap();
+
+        // finish.message is the message of the tag, notag skips it
+        {
+            let mut conf = repo.config().unwrap();
+            conf.set_str("gitflow.release.finish.message", "Release {version}").unwrap();
+            conf.set_bool("gitflow.hotfix.finish.notag", true).unwrap();
+            conf.set_bool("gitflow.hotfix.finish.push", true).unwrap();
+        }
+        assert_eq!(flow.config().unwrap().unsupported,
+                   vec!["gitflow.feature.finish.squash", "gitflow.hotfix.finish.push"]);
+        flow.finish(&GfBranch::Release, "1.0", &flow.finish_options(&GfBranch::Release)).unwrap();
+        let tag = repo.find_reference("refs/tags/1.0").unwrap().peel_to_tag().unwrap();
+        assert_eq!(tag.message(), Some("Release 1.0"));
+
+        flow.finish(&GfBranch::Hotfix, "1.0.1", &flow.finish_options(&GfBranch::Hotfix)).unwrap();
+        assert!(repo.find_reference("refs/tags/1.0.1").is_err());
+        assert!(repo.graph_descendant_of(flow.branch_head("develop").unwrap(), hotfix).unwrap());
+    }
+
+    #[test]
+    fn test_interrupted_init() {
+        let flow = set_test_flow("gf-flow-interrupted");
+        assert!(flow.is_initialized().unwrap());
+
+        // stopped halfway through the settings, before develop
+        flow.repo().checkout("master").unwrap();
+        flow.repo().delete_branch("develop").unwrap();
+        flow.repo().0.config().unwrap().remove("gitflow.prefix.hotfix").unwrap();
+        assert_eq!(flow.missing_settings().unwrap(), vec!["gitflow.prefix.hotfix", "refs/heads/develop"]);
+        match flow.check_initialized() {
+            Err(e @ Error::NotInitialized(_)) => assert_eq!(e.exit_code(), 3),
+            _ => panic!("a half done init is not an init"),
+        }
+
+        flow.init(&flow.init_options().unwrap()).unwrap();
+        assert!(flow.is_initialized().unwrap());
+        assert_eq!(flow.prefix(&GfBranch::Hotfix).unwrap(), "hotfix/");
+    }
+
+    #[test]
+    fn test_init_clone() {
+        let origin = set_test_flow("gf-flow-clone-origin");
+        origin.repo().checkout("master").unwrap();
+        commit_file(&origin, ".gitflow", "[gitflow \"branch\"]\n\tmaster = master\n\tdevelop = develop\n");
+        origin.repo().checkout("develop").unwrap();
+        commit_file(&origin, "d.txt", "d");
+        origin.repo().checkout("master").unwrap();
+
+        let path = env::temp_dir().join("gf-flow-clone");
+        let _ = remove_dir_all(&path);
+        Repository::clone(origin.repo().get_workdir().unwrap().to_str().unwrap(), &path).unwrap();
+        let flow = Flow::open(&path).unwrap();
+        let repo = &flow.repo().0;
+        assert!(flow.missing_settings().unwrap().contains(
+                &"refs/heads/develop (init creates it from origin/develop)".to_string()));
+
+        // develop comes from the remote, with the commits master lacks
+        let opts = flow.init_options().unwrap();
+        let plan = flow.plan_init(&opts).unwrap();
+        assert!(plan.steps.iter().any(|s| matches!(s, Step::Create { branch, base }
+                    if branch == "develop" && base == "origin/develop")));
+        flow.init(&opts).unwrap();
+        assert_eq!(flow.branch_head("develop").unwrap(), origin.branch_head("develop").unwrap());
+        assert_eq!(flow.repo().get_config("branch.develop.remote").unwrap(), "origin");
+        assert!(repo.find_branch("develop", git2::BranchType::Local).unwrap().upstream().is_ok());
+        assert!(flow.is_initialized().unwrap());
+    }
+}
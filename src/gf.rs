@@ -1,9 +1,32 @@
-use std::path::PathBuf;
-use crate::gitc::{
-    GitcRepo,
+use std::{
+    io::{
+        stdin,
+        stderr,
+        IsTerminal,
+        Write,
+    },
+    path::PathBuf,
+};
+use crate::flow::{
+    Flow,
+    InitOptions,
+    StartOptions,
+    FinishOptions,
+    DeleteOptions,
+    Step,
+};
+
+use crate::gitc::MergeKind;
+
+use crate::output::{
+    GfOutput,
+    Format,
 };
 
-use crate::utils::get_user_input;
+use crate::utils::{
+    get_user_input,
+    edit_message,
+};
 
 use crate::error::{
     Error,
@@ -11,64 +34,132 @@ use crate::error::{
 };
 
 // also use it as the first command
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum GfBranch {
     Feature,
     Bugfix,
     Hotfix,
     Release,
     Support,
+    // declared in the config as gitflow.type.<name>.*
+    Custom(String),
 }
 
-#[derive(Clone, Copy, PartialEq)]
+impl GfBranch {
+    // the built-in types, see Flow::branch_types for the user defined ones
+    pub fn all() -> &'static [GfBranch] {
+        &[
+            GfBranch::Feature,
+            GfBranch::Bugfix,
+            GfBranch::Release,
+            GfBranch::Hotfix,
+            GfBranch::Support,
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<GfBranch> {
+        GfBranch::all().iter().find(|b| b.name() == name).cloned()
+    }
+
+    // the name used by subcommands and config keys
+    pub fn name(&self) -> &str {
+        match self {
+            GfBranch::Feature => "feature",
+            GfBranch::Bugfix => "bugfix",
+            GfBranch::Hotfix => "hotfix",
+            GfBranch::Release => "release",
+            GfBranch::Support => "support",
+            GfBranch::Custom(name) => name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum GfCmds {
     Init,
     Start,
     Finish,
+    List,
+    Config,
+    Status,
+    Publish,
+    Track,
+    Checkout,
+    Delete,
+    Rename,
+    Log,
+    Undo,
+    Restore,
+    ArchiveList,
+    ArchivePrune,
+    Backport,
+    BackportContinue,
+    BackportAbort,
+    Rebase,
+    Diff,
 }
 
 pub struct GfWork {
     pub cmd: Option<GfBranch>,
     pub subcmd: Option<GfCmds>,
-    pub repo: GitcRepo,
+    pub flow: Flow,
     pub branch_suffix: String, // passed by user
+    pub fetch: bool,
+    pub force: bool,
+    pub force_worktree: bool, // delete a worktree with changes
+    pub base: Option<String>,
+    pub defaults: bool, // init without asking
+    pub worktree: bool, // start in a linked worktree
+    pub worktree_path: Option<PathBuf>,
+    pub new_name: Option<String>,
+    pub dry_run: bool,
+    pub older_than: Option<i64>, // in seconds
+    pub message: Option<String>,
+    pub no_edit: bool,
+    pub tag: Option<String>,
+    pub format: Format, // prompts only go with text output
+    pub release: Option<String>,
+    pub no_release: bool,
+    pub no_backmerge: bool,
+    pub source: Option<String>, // what to backport
+    pub lines: Vec<String>, // support branches to backport to
 }
 
 impl GfWork {
-    pub fn new(p: &PathBuf) -> Self {
+    pub fn new(mut flow: Flow) -> Self {
+        // progress goes to stderr, stdout only carries results
+        flow.set_progress(|s| {
+            eprint!("{}", s);
+            let _ = stderr().flush();
+        });
+
         Self {
             cmd: None,
             subcmd: None,
-            repo: GitcRepo::new(p),
+            flow,
             branch_suffix: String::with_capacity(10),
+            fetch: false,
+            force: false,
+            force_worktree: false,
+            base: None,
+            defaults: false,
+            worktree: false,
+            worktree_path: None,
+            new_name: None,
+            dry_run: false,
+            older_than: None,
+            message: None,
+            no_edit: false,
+            tag: None,
+            format: Format::Text,
+            release: None,
+            no_release: false,
+            no_backmerge: false,
+            source: None,
+            lines: Vec::new(),
         }
     }
 
-    fn get_branch_prefix(&self) -> Result<String> {
-        if let Some(bp) = self.cmd {
-            match bp {
-                GfBranch::Feature => {
-                    Ok(self.repo.get_config("gitflow.prefix.feature")?)
-                }
-                GfBranch::Bugfix => {
-                    Ok(self.repo.get_config("gitflow.prefix.bugfix")?)
-                }
-                GfBranch::Hotfix => {
-                    Ok(self.repo.get_config("gitflow.prefix.hotfix")?)
-                }
-                GfBranch::Release => {
-                    Ok(self.repo.get_config("gitflow.prefix.release")?)
-                }
-                GfBranch::Support => {
-                    Ok(self.repo.get_config("gitflow.prefix.support")?)
-                }
-            }
-        } else {
-            Err(Error::Generic("No cmd set to get branch prefix, set cmd firstly.".to_string()))
-        }
-
-    }
-
     pub fn set_branch_suffix(&mut self, bs: &str) {
         self.branch_suffix = bs.to_string();
     }
@@ -81,111 +172,358 @@ impl GfWork {
         self.subcmd = Some(subcmd);
     }
 
-    fn subconfig(&self, prompt: &str, name: &str, default: &str) -> Result<()> {
-        let mut s = get_user_input(prompt)?;
+    pub fn set_fetch(&mut self, fetch: bool) {
+        self.fetch = fetch;
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn set_force_worktree(&mut self, force_worktree: bool) {
+        self.force_worktree = force_worktree;
+    }
+
+    fn delete_options(&self) -> DeleteOptions {
+        DeleteOptions {
+            force: self.force,
+            force_worktree: self.force_worktree,
+        }
+    }
+
+    pub fn set_base(&mut self, base: Option<&str>) {
+        self.base = base.map(|b| b.to_string());
+    }
+
+    pub fn set_defaults(&mut self, defaults: bool) {
+        self.defaults = defaults;
+    }
+
+    pub fn set_worktree(&mut self, worktree: bool) {
+        self.worktree = worktree;
+    }
+
+    pub fn set_worktree_path(&mut self, path: Option<&str>) {
+        self.worktree_path = path.map(PathBuf::from);
+    }
+
+    pub fn set_new_name(&mut self, new_name: Option<&str>) {
+        self.new_name = new_name.map(|n| n.to_string());
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn set_older_than(&mut self, older_than: Option<i64>) {
+        self.older_than = older_than;
+    }
+
+    pub fn set_message(&mut self, message: Option<&str>) {
+        self.message = message.map(|m| m.to_string());
+    }
+
+    pub fn set_no_edit(&mut self, no_edit: bool) {
+        self.no_edit = no_edit;
+    }
+
+    pub fn set_tag(&mut self, tag: Option<&str>) {
+        self.tag = tag.map(|t| t.to_string());
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    pub fn set_release(&mut self, release: Option<&str>) {
+        self.release = release.map(|r| r.to_string());
+    }
+
+    pub fn set_no_release(&mut self, no_release: bool) {
+        self.no_release = no_release;
+    }
+
+    pub fn set_no_backmerge(&mut self, no_backmerge: bool) {
+        self.no_backmerge = no_backmerge;
+    }
+
+    pub fn set_source(&mut self, source: Option<&str>) {
+        self.source = source.map(|s| s.to_string());
+    }
+
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+    }
+
+    pub fn set_remote(&mut self, remote: Option<&str>) {
+        self.flow.set_remote(remote.map(|r| r.to_string()));
+    }
+
+    // ask for the setting `key`, unless it is set already and init isn't
+    // forced to ask again
+    fn subconfig(&self, key: &str, prompt: &str, default: &str) -> Result<String> {
+        if !self.force && self.flow.is_configured(key) {
+            return Ok(default.to_string());
+        }
+        let mut s = get_user_input(&format!("{} [{}]", prompt, default))?;
         if s.is_empty() {
             s.push_str(default);
         }
-        self.repo.config(name, &s)?;
 
-        Ok(())
+        Ok(s)
     }
 
-    fn config(&self) -> Result<()> {
-        self.subconfig("Branch name for production releases: [master] ", "gitflow.branch.master", "master")?;
-        self.subconfig("Branch name for \"next release\" development: [develop] ", "gitflow.branch.develop", "develop")?;
+    fn config(&self) -> Result<InitOptions> {
+        // what is set already, by git-flow AVH or in `.gitflow` too, is
+        // taken without asking
+        let mut opts = self.flow.init_options()?;
+        // and a repository initialized before, also by git-flow AVH or
+        // nvie, only gets the defaults of what it lacks, like everything
+        // does with -d. an interrupted init is taken up where it stopped
+        if self.defaults || (!self.force && self.flow.config().is_ok()) {
+            return Ok(opts);
+        }
+
+        opts.master = self.subconfig("gitflow.branch.master", "Branch name for production releases:", &opts.master)?;
+        opts.develop = self.subconfig("gitflow.branch.develop", "Branch name for \"next release\" development:", &opts.develop)?;
 
-        println!("How to name your supporting branch prefixes?");
-        self.subconfig("Feature branches? [feature/]", "gitflow.prefix.feature", "feature/")?;
-        self.subconfig("Bugfix branches? [bugfix/]", "gitflow.prefix.bugfix", "bugfix/")?;
-        self.subconfig("Release branches? [release/]", "gitflow.prefix.release", "release/")?;
-        self.subconfig("Hotfix branches? [hotfix/]", "gitflow.prefix.hotfix", "hotfix/")?;
-        self.subconfig("Support branches? [support/]", "gitflow.prefix.support", "support/")?;
+        let prefixes = ["feature", "bugfix", "release", "hotfix", "support"];
+        if self.force || prefixes.iter().any(|p| !self.flow.is_configured(&format!("gitflow.prefix.{}", p))) {
+            eprintln!("How to name your supporting branch prefixes?");
+        }
+        opts.feature = self.subconfig("gitflow.prefix.feature", "Feature branches?", &opts.feature)?;
+        opts.bugfix = self.subconfig("gitflow.prefix.bugfix", "Bugfix branches?", &opts.bugfix)?;
+        opts.release = self.subconfig("gitflow.prefix.release", "Release branches?", &opts.release)?;
+        opts.hotfix = self.subconfig("gitflow.prefix.hotfix", "Hotfix branches?", &opts.hotfix)?;
+        opts.support = self.subconfig("gitflow.prefix.support", "Support branches?", &opts.support)?;
 
-        self.subconfig("Version tag prefix? []", "gitflow.prefix.versiontag", "")?;
+        opts.versiontag = self.subconfig("gitflow.prefix.versiontag", "Version tag prefix?", &opts.versiontag)?;
 
-        let repodir = self.repo.get_workdir()?;
-        let hooksdir = format!("{}/.git/hooks", repodir.to_str().unwrap());
-        self.subconfig(&format!("Hooks and filters directory? [{}]", hooksdir), "gitflow.path.hooks", &hooksdir)?;
+        let hooksdir = match &opts.hooks {
+            Some(hooks) => hooks.clone(),
+            None => self.flow.default_hooks_dir()?,
+        };
+        opts.hooks = Some(self.subconfig("gitflow.path.hooks", "Hooks and filters directory?", &hooksdir)?);
 
-        Ok(())
+        opts.origin = self.subconfig("gitflow.origin", "Remote to publish branches to?", &opts.origin)?;
+
+        Ok(opts)
     }
 
-    fn cat_gfbranch(&self) -> Result<String> {
-        let branch_prefix = self.get_branch_prefix()?;
-        Ok(format!("{}{}", &branch_prefix, &self.branch_suffix))
+    fn get_cmd(&self) -> Result<&GfBranch> {
+        self.cmd.as_ref().ok_or_else(|| Error::Generic("No branch_prefix supplied to work".to_string()))
     }
 
-    // The main api to do git-flow works
-    pub fn work(&self) -> Result<()> {
-        if self.subcmd.is_none() {
-            return Err(Error::Generic(format!("No subcommand supplied to work")));
+    // whether finishing makes a merge commit of the branch, the one the
+    // message is for, rather than only fast-forwarding
+    fn commits_merge(&self, kind: &GfBranch, name: &str, opts: &FinishOptions) -> Result<bool> {
+        let branch = self.flow.branch_name(kind, name)?;
+
+        Ok(self.flow.plan_finish(kind, name, opts)?.steps.iter().any(|s| matches!(s,
+            Step::Merge { from, kind: MergeKind::Normal, .. } if *from == branch)))
+    }
+
+    // the branch to work on: the one given, or the current one, whose
+    // prefix decides the type when no type is given
+    fn target(&self) -> Result<(GfBranch, String)> {
+        match (&self.cmd, self.branch_suffix.is_empty()) {
+            (Some(cmd), false) => Ok((cmd.clone(), self.branch_suffix.clone())),
+            (Some(cmd), true) => {
+                let (kind, name) = self.flow.current()?;
+                if kind != *cmd {
+                    return Err(Error::Generic(format!(
+                        "the current branch is not a {} branch, name the branch to work on", cmd.name())));
+                }
+                Ok((kind, name))
+            }
+            (None, true) => self.flow.current(),
+            (None, false) => self.flow.parse_branch(&self.branch_suffix)?
+                .ok_or_else(|| Error::Generic(format!("{} is not a git-flow branch", self.branch_suffix))),
         }
-        if self.cmd.is_none() && self.subcmd.unwrap() != GfCmds::Init {
-            return Err(Error::Generic(format!("No branch_prefix supplied to work")));
+    }
+
+    // The main api to do git-flow works
+    pub fn work(&self) -> Result<GfOutput> {
+        let subcmd = match self.subcmd {
+            Some(subcmd) => subcmd,
+            None => return Err(Error::Generic("No subcommand supplied to work".to_string())),
+        };
+
+        // everything but init needs an init that ran to the end
+        if subcmd != GfCmds::Init {
+            self.flow.check_initialized()?;
         }
-        match self.subcmd.unwrap() {
+
+        match subcmd {
+            GfCmds::Init if self.dry_run => {
+                Ok(GfOutput::DryRun(self.flow.plan_init(&self.config()?)?))
+            }
             GfCmds::Init => {
-                self.repo.init()?;
-                self.config()?;
-                Ok(())
+                self.flow.init(&self.config()?)?;
+                Ok(GfOutput::Init {
+                    path: self.flow.repo().0.workdir()
+                        .unwrap_or_else(|| self.flow.repo().0.path())
+                        .to_string_lossy().to_string(),
+                })
             }
             GfCmds::Start => {
-                let branch = self.cat_gfbranch()?;
-                // create a new branch
-                self.repo.branch(&branch)?;
-                // and checkout it
-                self.repo.checkout(&branch)?;
-                Ok(())
+                // the configured defaults, the flags can only turn them on
+                let defaults = self.flow.start_options(self.get_cmd()?);
+                let opts = StartOptions {
+                    fetch: self.fetch || defaults.fetch,
+                    base: self.base.clone(),
+                    worktree: self.worktree,
+                    worktree_path: self.worktree_path.clone(),
+                    ..defaults
+                };
+                if self.dry_run {
+                    return Ok(GfOutput::DryRun(self.flow.plan_start(self.get_cmd()?, &self.branch_suffix, &opts)?));
+                }
+                Ok(GfOutput::Start(self.flow.start(self.get_cmd()?, &self.branch_suffix, &opts)?))
             }
             GfCmds::Finish => {
-                let branch = self.cat_gfbranch()?;
-                // checkout to related branch
-                // and merge
-                match self.cmd.unwrap() {
-                    GfBranch::Release | GfBranch::Hotfix => {
-                        self.repo.checkout(&self.repo.get_config("gitflow.branch.master")?)?;
-                        let refname = format!("refs/heads/{}", &branch);
-                        let branch_ref = self.repo.0.find_reference(&refname)?;
-                        self.repo.merge(
-                            &self.repo.get_config("gitflow.branch.master")?,
-                            self.repo.0.reference_to_annotated_commit(&branch_ref)?,
-                            // TODO (too simple to get the merge message)
-                            &get_user_input("Input your merge message: ")?
-                        )?;
-
-                        // Give some tag message
-                        self.repo.tag(
-                            self.repo.0.refname_to_id(&format!("refs/heads/{}",
-                                    &self.repo.get_config("gitflow.branch.master")?))?,
-                            &get_user_input("Input a tag name")?,
-                        )?;
-
-                        self.repo.checkout(&self.repo.get_config("gitflow.branch.develop")?)?;
-                        let refname = format!("refs/heads/{}",
-                            &self.repo.get_config("gitflow.branch.master")?);
-                        let branch_ref = self.repo.0.find_reference(&refname)?;
-                        self.repo.merge(
-                            &self.repo.get_config("gitflow.branch.develop")?,
-                            self.repo.0.reference_to_annotated_commit(&branch_ref)?,
-                            &get_user_input("Input your merge message: ")?
-                        )?;
-                    },
-                    _ => {
-                        self.repo.checkout(&self.repo.get_config("gitflow.branch.develop")?)?;
-                        let refname = format!("refs/heads/{}", &branch);
-                        let branch_ref = self.repo.0.find_reference(&refname)?;
-                        self.repo.merge(
-                            &self.repo.get_config("gitflow.branch.develop")?,
-                            self.repo.0.reference_to_annotated_commit(&branch_ref)?,
-                            &get_user_input("Input your merge message: ")?
-                        )?;
+                let (cmd, name) = self.target()?;
+                let cmd = &cmd;
+                let defaults = self.flow.finish_options(cmd);
+                let mut opts = FinishOptions {
+                    fetch: self.fetch || defaults.fetch,
+                    message: self.message.clone(),
+                    tag: self.tag.clone(),
+                    release: self.release.clone(),
+                    no_release: self.no_release,
+                    no_backmerge: self.no_backmerge || defaults.no_backmerge,
+                    ..defaults
+                };
+                // like git commit, ask and edit only when run by someone at
+                // a terminal who reads the text output
+                let interactive = !self.dry_run && self.format == Format::Text && stdin().is_terminal();
+                if opts.tag.is_none() && !opts.no_tag && interactive && self.flow.tags_on_finish(cmd) {
+                    let default = self.flow.finish_tag(&name, &opts)?;
+                    let tag = get_user_input(&format!("Tag name [{}]", default))?;
+                    if !tag.is_empty() {
+                        opts.tag = Some(tag);
                     }
                 }
-                // then delete the git-flow branch
-                self.repo.delete_branch(&branch)?;
-
-                Ok(())
+                if opts.message.is_none() && !self.no_edit && interactive && self.commits_merge(cmd, &name, &opts)? {
+                    let initial = self.flow.merge_message(cmd, &name, &opts)?;
+                    let path = self.flow.repo().0.path().join("GITFLOW_MERGE_MSG");
+                    opts.message = Some(edit_message(&self.flow.editor(), &path, &initial)?);
+                }
+                if self.dry_run {
+                    return Ok(GfOutput::DryRun(self.flow.plan_finish(cmd, &name, &opts)?));
+                }
+                Ok(GfOutput::Finish(self.flow.finish(cmd, &name, &opts)?))
+            }
+            GfCmds::List => {
+                let cmd = self.get_cmd()?;
+                Ok(GfOutput::List {
+                    kind: cmd.name().to_string(),
+                    base: self.flow.base_of(cmd)?,
+                    branches: self.flow.list(cmd)?,
+                })
+            }
+            GfCmds::Config => {
+                Ok(GfOutput::Config(self.flow.config()?))
+            }
+            GfCmds::Status => {
+                Ok(GfOutput::Status(self.flow.status()?))
+            }
+            GfCmds::Log => {
+                Ok(GfOutput::Log {
+                    entries: self.flow.log()?,
+                })
+            }
+            GfCmds::Undo if self.dry_run => {
+                Ok(GfOutput::DryRun(self.flow.plan_undo(self.force)?))
+            }
+            GfCmds::Undo => {
+                Ok(GfOutput::Undo(self.flow.undo(self.force)?))
+            }
+            GfCmds::Restore if self.dry_run => {
+                Ok(GfOutput::DryRun(self.flow.plan_restore(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::Restore => {
+                Ok(GfOutput::Restore(self.flow.restore(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::ArchiveList => {
+                Ok(GfOutput::Archive {
+                    archives: self.flow.archives()?,
+                })
+            }
+            GfCmds::ArchivePrune => {
+                let age = self.older_than
+                    .ok_or_else(|| Error::Generic("No age supplied to prune".to_string()))?;
+                if self.dry_run {
+                    return Ok(GfOutput::DryRun(self.flow.plan_prune_archives(age)?));
+                }
+                Ok(GfOutput::Prune {
+                    pruned: self.flow.prune_archives(age)?,
+                })
+            }
+            GfCmds::Backport => {
+                let source = self.source.as_deref()
+                    .ok_or_else(|| Error::Generic("No commit supplied to backport".to_string()))?;
+                if self.dry_run {
+                    return Ok(GfOutput::DryRun(self.flow.plan_backport(source, &self.lines)?));
+                }
+                Ok(GfOutput::Backport(self.flow.backport(source, &self.lines)?))
+            }
+            GfCmds::BackportContinue | GfCmds::BackportAbort if self.dry_run => {
+                Err(Error::Generic("--dry-run only plans a new backport".to_string()))
+            }
+            GfCmds::BackportContinue => {
+                Ok(GfOutput::Backport(self.flow.backport_continue()?))
+            }
+            GfCmds::BackportAbort => {
+                Ok(GfOutput::Backport(self.flow.backport_abort()?))
+            }
+            GfCmds::Publish if self.dry_run => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::DryRun(self.flow.plan_publish(&cmd, &name)?))
+            }
+            GfCmds::Track if self.dry_run => {
+                Ok(GfOutput::DryRun(self.flow.plan_track(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::Checkout if self.dry_run => {
+                Ok(GfOutput::DryRun(self.flow.plan_checkout(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::Delete if self.dry_run => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::DryRun(self.flow.plan_delete(&cmd, &name, &self.delete_options())?))
+            }
+            GfCmds::Rebase if self.dry_run => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::DryRun(self.flow.plan_rebase(&cmd, &name)?))
+            }
+            GfCmds::Publish => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::Publish(self.flow.publish(&cmd, &name)?))
+            }
+            GfCmds::Rebase => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::Rebase(self.flow.rebase(&cmd, &name)?))
+            }
+            GfCmds::Diff => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::Diff(self.flow.diff(&cmd, &name)?))
+            }
+            GfCmds::Track => {
+                Ok(GfOutput::Track(self.flow.track(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::Checkout => {
+                Ok(GfOutput::Checkout(self.flow.checkout(self.get_cmd()?, &self.branch_suffix)?))
+            }
+            GfCmds::Delete => {
+                let (cmd, name) = self.target()?;
+                Ok(GfOutput::Delete(self.flow.delete(&cmd, &name, &self.delete_options())?))
+            }
+            GfCmds::Rename => {
+                let new_name = self.new_name.as_deref()
+                    .ok_or_else(|| Error::Generic("No new name supplied to rename".to_string()))?;
+                if self.dry_run {
+                    return Ok(GfOutput::DryRun(self.flow.plan_rename(self.get_cmd()?, &self.branch_suffix, new_name)?));
+                }
+                Ok(GfOutput::Rename(self.flow.rename(self.get_cmd()?, &self.branch_suffix, new_name)?))
             }
         }
     }
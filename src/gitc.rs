@@ -8,18 +8,24 @@ use crate::{
 };
 
 use std:: {
+    env,
+    fs,
+    ops::Deref,
     path::{
         Path,
         PathBuf,
     },
-    io::{
-        Write,
-        stdout,
-    }
+    process,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
 };
 
 use git2::{
+    Config,
     Repository,
+    RepositoryOpenFlags,
     Signature,
     ErrorCode,
     Oid,
@@ -34,7 +40,13 @@ use git2::{
     Cred,
     RebaseOptions,
     BranchType,
+    StatusOptions,
+    CredentialType,
+    Time,
+    DiffFormat,
+    CherrypickOptions,
 };
+use serde::Serialize;
 
 pub struct GitcRepo(pub Repository);
 
@@ -44,24 +56,119 @@ impl From<Repository> for GitcRepo {
     }
 }
 
-impl Into<Repository> for GitcRepo {
-    fn into(self) -> Repository {
-        self.0
+impl From<GitcRepo> for Repository {
+    fn from(repo: GitcRepo) -> Self {
+        repo.0
     }
 }
 
+// The way a merge was (or would be) carried out
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeKind {
+    FastForward,
+    Normal,
+    Conflict,
+    UpToDate,
+}
+
+// The team settings committed at the top of the working tree, in git
+// config syntax
+pub const SHARED_CONFIG: &str = ".gitflow";
+
+// only git-flow settings may come from a committed file, others like
+// `core.editor` would let a commit run commands, and no paths, they
+// differ from clone to clone
+fn shareable(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.starts_with("gitflow.") && !name.starts_with("gitflow.path.")
+}
+
+// A worktree of the repository, like a line of `git worktree list`
+#[derive(Clone, Debug)]
+pub struct Worktree {
+    // the top of its working tree
+    pub path: PathBuf,
+    // its own git directory, the common one for the main worktree
+    pub gitdir: PathBuf,
+    // the branch checked out in it, `None` when detached
+    pub branch: Option<String>,
+    // added by `git worktree add`, not the main one
+    pub linked: bool,
+}
+
+// A throwaway bare repository borrowing the objects of another one through
+// `objects/info/alternates`. what is written to it goes away with it, so
+// previews can commit there without leaving dangling objects behind
+pub struct Scratch {
+    repo: GitcRepo,
+    path: PathBuf,
+}
+
+impl Deref for Scratch {
+    type Target = GitcRepo;
+
+    fn deref(&self) -> &GitcRepo {
+        &self.repo
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// numbers the scratch repositories of this process
+static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+// the branch HEAD points to in `gitdir`
+fn head_branch(gitdir: &Path) -> Option<String> {
+    let head = fs::read_to_string(gitdir.join("HEAD")).ok()?;
+    head.trim().strip_prefix("ref: refs/heads/").map(|b| b.to_string())
+}
+
 impl GitcRepo {
-    // create a new git repo
-    pub fn new(p: &PathBuf) -> Self {
-        let repo = Repository::open(&p);
-        let repo = match repo {
+    // the repository at or above `p`, a new one is created at `p` when
+    // there is none. only init may create repositories
+    pub fn create(p: &Path) -> Result<Self> {
+        match Repository::discover(p) {
+            Ok(repo) => Ok(GitcRepo::from(repo)),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(GitcRepo::from(Repository::init(p)?)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the repository git would use: the one in `GIT_DIR`, or the one found
+    // from the current directory upwards, stopping at
+    // `GIT_CEILING_DIRECTORIES`. `GIT_WORK_TREE` overrides its working tree
+    pub fn discover() -> Result<Self> {
+        let found = match env::var_os("GIT_DIR") {
+            Some(dir) => Repository::open(&dir),
+            None => Repository::open_ext(
+                ".",
+                RepositoryOpenFlags::empty(),
+                env::split_paths(&env::var_os("GIT_CEILING_DIRECTORIES").unwrap_or_default()),
+            ),
+        };
+        let repo = match found {
             Ok(repo) => repo,
-            Err(e) => {
-                println!("{}, create a new one", e);
-                Repository::init(&p).unwrap()
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                let cwd = env::current_dir()?;
+                return Err(Error::NotRepository(cwd.to_string_lossy().to_string()));
             }
+            Err(e) => return Err(e.into()),
         };
-        GitcRepo::from(repo)
+        if let Some(tree) = env::var_os("GIT_WORK_TREE") {
+            repo.set_workdir(Path::new(&tree), false)?;
+        }
+
+        Ok(GitcRepo::from(repo))
+    }
+
+    // open an existing git repo, never create one
+    pub fn open(p: &Path) -> Result<Self> {
+        Ok(GitcRepo::from(Repository::open(p)?))
     }
 
     pub fn init(&self) -> Result<()> {
@@ -87,10 +194,15 @@ impl GitcRepo {
     }
 
     pub fn checkout(&self, branch: &str) -> Result<()> {
+        self.get_workdir()?;
         let repo = &self.0;
         let refname = format!("refs/heads/{}", branch);
+        if repo.find_reference(&refname).is_err() {
+            return Err(Error::BranchNotFound(branch.to_string()));
+        }
+        // update the working tree first, HEAD is the baseline it moves away from
+        repo.checkout_tree(&repo.revparse_single(&refname)?, None)?;
         repo.set_head(&refname)?;
-        repo.checkout_head(None)?;
 
         Ok(())
     }
@@ -98,15 +210,28 @@ impl GitcRepo {
     pub fn branch(&self, branch: &str) -> Result<()> {
         let repo = &self.0;
         if let Some(oid) = repo.head()?.target() {
-            let commit = repo.find_commit(oid)?;
             // If force is true and a reference already exists with the given name, it'll be replaced.
             // Don't replace the reference already exists in this case.
-            repo.branch(branch, &commit, false)?;
+            self.branch_from(branch, oid)?;
         }
 
         Ok(())
     }
 
+    pub fn branch_from(&self, branch: &str, oid: Oid) -> Result<()> {
+        let commit = self.0.find_commit(oid)?;
+        self.0.branch(branch, &commit, false).map_err(|e| match e.code() {
+            ErrorCode::Exists => Error::BranchExists(branch.to_string()),
+            ErrorCode::InvalidSpec => Error::InvalidName {
+                name: branch.to_string(),
+                reason: e.message().to_string(),
+            },
+            _ => Error::Git(e),
+        })?;
+
+        Ok(())
+    }
+
     pub fn config(&self, name: &str, value: &str) -> Result<()> {
         let mut conf = self.0.config()?;
         conf.set_str(name, value)?;
@@ -114,15 +239,260 @@ impl GitcRepo {
         Ok(())
     }
 
+    // git config first, then the shared settings
     pub fn get_config(&self, name: &str) -> Result<String> {
         let conf = self.0.config()?;
 
-        Ok(conf.get_string(name)?)
+        match conf.get_string(name) {
+            Err(e) if e.code() == ErrorCode::NotFound && shareable(name) => match self.shared_config()? {
+                Some(shared) => Ok(shared.get_string(name)?),
+                None => Err(e.into()),
+            },
+            v => Ok(v?),
+        }
     }
 
+    // the `.gitflow` file of the working tree, if there is one
+    pub fn shared_config(&self) -> Result<Option<Config>> {
+        let path = match self.0.workdir() {
+            Some(dir) => dir.join(SHARED_CONFIG),
+            None => return Ok(None),
+        };
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        Ok(Some(Config::open(&path)?))
+    }
+
+    // a setting of the `.gitflow` file, git config doesn't count
+    pub fn get_shared(&self, name: &str) -> Result<Option<String>> {
+        if !shareable(name) {
+            return Ok(None);
+        }
+        match self.shared_config()? {
+            Some(shared) => match shared.get_string(name) {
+                Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+                v => Ok(Some(v?)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // no staged or unstaged changes, untracked files don't count
+    pub fn is_clean(&self) -> Result<bool> {
+        self.get_workdir()?;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(false).include_ignored(false);
+
+        Ok(self.0.statuses(Some(&mut opts))?.is_empty())
+    }
+
+    pub fn get_config_bool(&self, name: &str) -> Result<bool> {
+        let conf = self.0.config()?;
+
+        match conf.get_bool(name) {
+            Err(e) if e.code() == ErrorCode::NotFound && shareable(name) => match self.shared_config()? {
+                Some(shared) => Ok(shared.get_bool(name)?),
+                None => Err(e.into()),
+            },
+            v => Ok(v?),
+        }
+    }
+
+    // names of all the config entries matching a regex, the shared
+    // settings included
+    pub fn config_names(&self, regex: &str) -> Result<Vec<String>> {
+        let conf = self.0.config()?;
+        let mut names = Vec::new();
+        let entries = conf.entries(Some(regex))?;
+
+        for entry in &entries {
+            if let Some(name) = entry?.name() {
+                names.push(name.to_string());
+            }
+        }
+        if let Some(shared) = self.shared_config()? {
+            for entry in &shared.entries(Some(regex))? {
+                if let Some(name) = entry?.name() {
+                    if shareable(name) && !names.iter().any(|n| n == name) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    // the working tree, bare repositories have none
     pub fn get_workdir(&self) -> Result<&Path> {
-        // Don't care about bare repo
-        Ok(self.0.workdir().unwrap())
+        self.0.workdir().ok_or_else(|| Error::NoWorkTree(self.0.path().to_string_lossy().to_string()))
+    }
+
+    // the git directory all the worktrees share, with the refs, the config
+    // and the hooks. a linked worktree has its own git directory under
+    // `worktrees/` which points back to it in `commondir`
+    pub fn common_dir(&self) -> PathBuf {
+        let gitdir = self.0.path();
+        match fs::read_to_string(gitdir.join("commondir")) {
+            Ok(common) => {
+                let common = gitdir.join(common.trim());
+                fs::canonicalize(&common).unwrap_or(common)
+            }
+            Err(_) => gitdir.to_path_buf(),
+        }
+    }
+
+    // the main worktree, unless the repository is bare, and the linked ones
+    // that still exist
+    pub fn worktrees(&self) -> Result<Vec<Worktree>> {
+        let common = self.common_dir();
+        let mut trees = Vec::new();
+        if let Some(path) = Repository::open(&common)?.workdir() {
+            trees.push(Worktree {
+                path: path.to_path_buf(),
+                branch: head_branch(&common),
+                gitdir: common.clone(),
+                linked: false,
+            });
+        }
+
+        let dirs = match fs::read_dir(common.join("worktrees")) {
+            Ok(dirs) => dirs,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(trees),
+            Err(e) => return Err(e.into()),
+        };
+        for dir in dirs {
+            let gitdir = dir?.path();
+            // `gitdir` has the path of the `.git` file at the top of it
+            let dotgit = match fs::read_to_string(gitdir.join("gitdir")) {
+                Ok(dotgit) => PathBuf::from(dotgit.trim()),
+                Err(_) => continue,
+            };
+            let path = match dotgit.parent() {
+                Some(path) if path.exists() => path.to_path_buf(),
+                // left for `git worktree prune`
+                _ => continue,
+            };
+            trees.push(Worktree {
+                path,
+                branch: head_branch(&gitdir),
+                gitdir,
+                linked: true,
+            });
+        }
+
+        Ok(trees)
+    }
+
+    // whether `tree` is the worktree this repository was opened in
+    pub fn is_current(&self, tree: &Worktree) -> bool {
+        let same = |a: &Path, b: &Path| match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        same(self.0.path(), &tree.gitdir)
+    }
+
+    // check `branch` out in a new linked worktree at `path`, laid out the
+    // way `git worktree add` does it
+    pub fn add_worktree(&self, path: &Path, branch: &str) -> Result<()> {
+        if path.exists() && fs::read_dir(path)?.next().is_some() {
+            return Err(Error::Generic(format!("{} already exists and is not empty", path.display())));
+        }
+        fs::create_dir_all(path)?;
+        let path = fs::canonicalize(path)?;
+
+        // named after the directory, numbered when that is taken
+        let worktrees = self.common_dir().join("worktrees");
+        let name = path.file_name().map_or("worktree".to_string(), |n| n.to_string_lossy().to_string());
+        let mut id = name.clone();
+        let mut n = 1;
+        while worktrees.join(&id).exists() {
+            id = format!("{}{}", name, n);
+            n += 1;
+        }
+        let gitdir = worktrees.join(&id);
+        fs::create_dir_all(&gitdir)?;
+        fs::write(gitdir.join("commondir"), "../..\n")?;
+        fs::write(gitdir.join("gitdir"), format!("{}\n", path.join(".git").display()))?;
+        fs::write(gitdir.join("HEAD"), format!("ref: refs/heads/{}\n", branch))?;
+        fs::write(path.join(".git"), format!("gitdir: {}\n", gitdir.display()))?;
+
+        // fill its working tree and index, or leave nothing behind
+        let filled = Repository::open(&path)
+            .and_then(|tree| tree.checkout_head(Some(CheckoutBuilder::new().force())));
+        if let Err(e) = filled {
+            let _ = fs::remove_dir_all(&path);
+            let _ = fs::remove_dir_all(&gitdir);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    // why the linked worktree `tree` can't be removed, changes in it are
+    // only thrown away when `force` is set
+    pub fn check_removable(&self, tree: &Worktree, force: bool) -> Result<()> {
+        let path = tree.path.display();
+        if !tree.linked {
+            return Err(Error::Generic(format!("{} is the main worktree", path)));
+        }
+        if self.is_current(tree) {
+            return Err(Error::Generic(format!(
+                        "{} is the current worktree, run the command from another one", path)));
+        }
+        if tree.gitdir.join("locked").exists() {
+            return Err(Error::Generic(format!("the worktree {} is locked", path)));
+        }
+        if !force {
+            let mut opts = StatusOptions::new();
+            opts.include_untracked(true).include_ignored(false);
+            if !Repository::open(&tree.path)?.statuses(Some(&mut opts))?.is_empty() {
+                return Err(Error::Generic(format!(
+                            "the worktree {} has uncommitted or untracked files", path)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // remove the linked worktree `tree` and its git directory, see
+    // `check_removable` first
+    pub fn remove_worktree(&self, tree: &Worktree) -> Result<()> {
+        fs::remove_dir_all(&tree.path)?;
+        fs::remove_dir_all(&tree.gitdir)?;
+
+        Ok(())
+    }
+
+    // point the HEAD of the linked worktree `tree` at `head`, a commit id or
+    // `ref: <refname>`, returning what it was
+    pub fn set_worktree_head(&self, tree: &Worktree, head: &str) -> Result<String> {
+        let path = tree.gitdir.join("HEAD");
+        let old = fs::read_to_string(&path)?;
+        fs::write(&path, format!("{}\n", head))?;
+
+        Ok(old.trim_end().to_string())
+    }
+
+    // where git runs hooks from: `core.hooksPath`, relative to the top of the
+    // working tree like git does, else `hooks` in the common git directory
+    pub fn hooks_dir(&self) -> PathBuf {
+        let path = match self.get_config("core.hooksPath") {
+            Ok(path) => path,
+            Err(_) => return self.common_dir().join("hooks"),
+        };
+        let path = match (path.strip_prefix("~/"), env::var_os("HOME")) {
+            (Some(rest), Some(home)) => Path::new(&home).join(rest),
+            _ => PathBuf::from(path),
+        };
+        if path.is_absolute() {
+            return path;
+        }
+
+        self.0.workdir().unwrap_or_else(|| self.0.path()).join(path)
     }
 
     fn signature_allow_undefined_name(
@@ -166,41 +536,56 @@ impl GitcRepo {
         &self,
         oid: Oid,
         tag: &str,
+        msg: &str,
     ) -> Result<()> {
         let repo = &self.0;
         let signature = self.signature_allow_undefined_name()?;
         let target = repo.find_object(oid, Some(ObjectType::Commit))?;
 
-        repo.tag(tag, &target, &signature, "", false)?;
+        repo.tag(tag, &target, &signature, msg, false).map_err(|e| match e.code() {
+            ErrorCode::Exists => Error::TagExists(tag.to_string()),
+            ErrorCode::InvalidSpec => Error::InvalidName {
+                name: tag.to_string(),
+                reason: e.message().to_string(),
+            },
+            _ => Error::Git(e),
+        })?;
 
         Ok(())
     }
 
+    // fetch `refs` from `remote_name`, the progress is handed to `progress`
+    // as text to write to a terminal as is
     pub fn fetch(
         &self,
         remote_name: &str,
-        refs: &[&str]
+        refs: &[&str],
+        progress: Option<&dyn Fn(&str)>,
     ) -> Result<()> {
         let repo = &self.0;
+        let report = |s: &str| {
+            if let Some(progress) = progress {
+                progress(s);
+            }
+        };
         let mut remote = repo.find_remote(remote_name)?;
-        let mut cb = RemoteCallbacks::new();
+        let mut cb = self.remote_callbacks()?;
         cb.transfer_progress(|stats| {
             if stats.received_objects() == stats.total_objects() {
-                print!(
+                report(&format!(
                     "Resolving deltas {}/{}\r",
                     stats.indexed_deltas(),
                     stats.total_deltas()
-                    );
+                    ));
             } else if stats.total_objects() > 0 {
-                print!(
+                report(&format!(
                     "Received {}/{} objects ({}) in {} bytes\r",
                     stats.received_objects(),
                     stats.total_objects(),
                     stats.indexed_objects(),
                     stats.received_bytes()
-                    );
+                    ));
             }
-            stdout().flush().unwrap();
             true
         });
 
@@ -210,34 +595,62 @@ impl GitcRepo {
         fo.download_tags(AutotagOption::All);
 
         remote.fetch(refs, Some(&mut fo), None)?;
+        drop(fo);
 
         let stats = remote.stats();
         if stats.local_objects() > 0 {
-            println!(
-                "\rReceived {}/{} objects in {} bytes (used {} local objects)",
+            report(&format!(
+                "\rReceived {}/{} objects in {} bytes (used {} local objects)\n",
                 stats.indexed_objects(),
                 stats.total_objects(),
                 stats.received_bytes(),
                 stats.local_objects()
-            );
+            ));
         } else {
-            println!(
-                "\rReceived {}/{} objects in {} bytes",
+            report(&format!(
+                "\rReceived {}/{} objects in {} bytes\n",
                 stats.indexed_objects(),
                 stats.total_objects(),
                 stats.received_bytes()
-            );
+            ));
         }
 
         Ok(())
     }
 
+    // credentials from the ssh agent or the git credential helpers
+    fn remote_callbacks(&self) -> Result<RemoteCallbacks<'_>> {
+        let config = self.0.config()?;
+        let mut cb = RemoteCallbacks::new();
+        // libgit2 asks again after every rejected credential, each kind is
+        // offered once so a wrong one ends in an error instead of a loop
+        let mut tried = CredentialType::empty();
+        cb.credentials(move |url, username_from_url, allowed_types| {
+            let untried = allowed_types - tried;
+            if untried.contains(CredentialType::SSH_KEY) {
+                tried |= CredentialType::SSH_KEY;
+                Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"))
+            } else if untried.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                tried |= CredentialType::USER_PASS_PLAINTEXT;
+                Cred::credential_helper(&config, url, username_from_url)
+            } else if untried.contains(CredentialType::DEFAULT) {
+                tried |= CredentialType::DEFAULT;
+                Cred::default()
+            } else {
+                Err(git2::Error::from_str(&format!(
+                    "authentication to {} failed, check the ssh agent or the credential helper", url)))
+            }
+        });
+
+        Ok(cb)
+    }
+
     fn normal_merge(
         &self,
         commit_a: &AnnotatedCommit,
         commit_b: &AnnotatedCommit,
         msg: &str
-    ) -> Result<()> {
+    ) -> Result<MergeKind> {
         let repo = &self.0;
         let tree_a = repo.find_commit(commit_a.id())?.tree()?;
         let tree_b = repo.find_commit(commit_b.id())?.tree()?;
@@ -247,9 +660,12 @@ impl GitcRepo {
         let mut idx = repo.merge_trees(&ancestor, &tree_a, &tree_b, None)?;
 
         if idx.has_conflicts() {
-            println!("Merge conflicts detected...");
-            repo.checkout_index(Some(&mut idx), None)?;
-            return Ok(());
+            // leave the conflict markers in the working tree
+            repo.checkout_index(Some(&mut idx), Some(
+                    CheckoutBuilder::default()
+                    .allow_conflicts(true)
+                    .conflict_style_merge(true)))?;
+            return Ok(MergeKind::Conflict);
         }
 
         let tree = repo.find_tree(idx.write_tree_to(repo)?)?;
@@ -261,12 +677,12 @@ impl GitcRepo {
             Some("HEAD"),
             &signature,
             &signature,
-            &msg,
+            msg,
             &tree,
             &[&commit_a_bar, &commit_b_bar],
         )?;
 
-        Ok(())
+        Ok(MergeKind::Normal)
     }
 
     fn fast_forward_merge(
@@ -295,7 +711,7 @@ impl GitcRepo {
         branch: &str,
         commit: AnnotatedCommit,
         msg: &str
-    ) -> Result<()> {
+    ) -> Result<MergeKind> {
         let repo = &self.0;
         let analysis = repo.merge_analysis(&[&commit])?;
 
@@ -321,18 +737,230 @@ impl GitcRepo {
                     ))?;
                 }
             };
+
+            Ok(MergeKind::FastForward)
         } else if analysis.0.is_normal() {
             let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-            self.normal_merge(&head_commit, &commit, msg)?;
+            self.normal_merge(&head_commit, &commit, msg)
         } else {
-            println!("No merge to do...");
+            Ok(MergeKind::UpToDate)
+        }
+    }
+
+    // a scratch repository to try things in, see `Scratch`
+    pub fn scratch(&self) -> Result<Scratch> {
+        let path = env::temp_dir().join(format!(
+                "git-flow-scratch-{}-{}", process::id(), SCRATCH.fetch_add(1, Ordering::SeqCst)));
+        let _ = fs::remove_dir_all(&path);
+        // picks are committed as whoever runs the command
+        let (name, email) = match self.signature_allow_undefined_name() {
+            Ok(sig) => (sig.name().unwrap_or("git-flow").to_string(), sig.email().unwrap_or("git-flow").to_string()),
+            Err(_) => ("git-flow".to_string(), "git-flow".to_string()),
+        };
+
+        let created = Repository::init_bare(&path)
+            .map_err(Error::from)
+            .and_then(|repo| {
+                fs::write(
+                    repo.path().join("objects").join("info").join("alternates"),
+                    format!("{}\n", self.common_dir().join("objects").display()),
+                )?;
+                let mut conf = repo.config()?;
+                conf.set_str("user.name", &name)?;
+                conf.set_str("user.email", &email)?;
+                // opened again, the alternates are only read when the
+                // objects are first looked at
+                Ok(Repository::open_bare(&path)?)
+            });
+
+        match created {
+            Ok(repo) => Ok(Scratch {
+                repo: GitcRepo::from(repo),
+                path,
+            }),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&path);
+                Err(e)
+            }
+        }
+    }
+
+    // what merging `from` into `into` would do, without touching refs or
+    // the working tree, and where `into` would end up (`None` on conflicts).
+    // a normal merge is written as a commit so later previews can build on
+    // it, run it in a `Scratch` to keep it out of the repository
+    pub fn merge_preview(&self, into: Oid, from: Oid) -> Result<(MergeKind, Option<Oid>)> {
+        let repo = &self.0;
+
+        if into == from || repo.graph_descendant_of(into, from)? {
+            return Ok((MergeKind::UpToDate, Some(into)));
+        }
+        if repo.graph_descendant_of(from, into)? {
+            return Ok((MergeKind::FastForward, Some(from)));
         }
 
+        let commit_a = repo.find_commit(into)?;
+        let commit_b = repo.find_commit(from)?;
+        let ancestor = repo.find_commit(repo.merge_base(into, from)?)?.tree()?;
+        let mut idx = repo.merge_trees(&ancestor, &commit_a.tree()?, &commit_b.tree()?, None)?;
+        if idx.has_conflicts() {
+            return Ok((MergeKind::Conflict, None));
+        }
+
+        let tree = repo.find_tree(idx.write_tree_to(repo)?)?;
+        let signature = Signature::new("git-flow", "git-flow", &Time::new(0, 0))?;
+        let oid = repo.commit(None, &signature, &signature, "preview", &tree, &[&commit_a, &commit_b])?;
+
+        Ok((MergeKind::Normal, Some(oid)))
+    }
+
+    // a hash of what a commit changes against its first parent, ignoring
+    // whitespace and line numbers, so it is the same for cherry-picks of
+    // it like `git patch-id --stable`
+    pub fn patch_id(&self, oid: Oid) -> Result<Oid> {
+        let repo = &self.0;
+        let commit = repo.find_commit(oid)?;
+        let parent = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+        let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+
+        let mut buf = Vec::new();
+        diff.print(DiffFormat::Patch, |delta, _, line| {
+            match line.origin() {
+                'F' => {
+                    if let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) {
+                        buf.extend_from_slice(path.to_string_lossy().as_bytes());
+                    }
+                }
+                origin @ '+' | origin @ '-' => {
+                    buf.push(origin as u8);
+                    buf.extend(line.content().iter().filter(|b| !b.is_ascii_whitespace()));
+                }
+                _ => {}
+            }
+            true
+        })?;
+
+        Ok(Oid::hash_object(ObjectType::Blob, &buf)?)
+    }
+
+    // the tree of cherry-picking `oid` onto `onto`, without touching refs
+    // or the working tree, `None` on conflicts
+    pub fn pick_tree(&self, oid: Oid, onto: Oid) -> Result<Option<Oid>> {
+        let repo = &self.0;
+        let commit = repo.find_commit(oid)?;
+        if commit.parent_count() == 0 {
+            return Err(Error::Generic(format!("{} has no parent to pick it from", oid)));
+        }
+
+        let mut idx = repo.merge_trees(
+            &commit.parent(0)?.tree()?,
+            &repo.find_commit(onto)?.tree()?,
+            &commit.tree()?,
+            None,
+        )?;
+        if idx.has_conflicts() {
+            return Ok(None);
+        }
+
+        Ok(Some(idx.write_tree_to(repo)?))
+    }
+
+    // commit `tree` on top of `onto` as the cherry-pick of `oid`, keeping
+    // its author and noting where it came from like `git cherry-pick -x`.
+    // no ref is updated
+    pub fn pick_commit(&self, oid: Oid, onto: Oid, tree: Oid) -> Result<Oid> {
+        let repo = &self.0;
+        let commit = repo.find_commit(oid)?;
+        let committer = self.signature_allow_undefined_name()?;
+        let msg = format!(
+            "{}\n\n(cherry picked from commit {})\n",
+            commit.message().unwrap_or("").trim_end(),
+            oid,
+        );
+
+        let oid = repo.commit(
+            None,
+            &commit.author(),
+            &committer,
+            &msg,
+            &repo.find_tree(tree)?,
+            &[&repo.find_commit(onto)?],
+        )?;
+
+        Ok(oid)
+    }
+
+    // cherry-pick `oid` onto the checked out branch in the working tree,
+    // leaving the conflicts to be resolved like `git cherry-pick` does
+    pub fn cherrypick_conflicts(&self, oid: Oid) -> Result<()> {
+        let repo = &self.0;
+        let mut opts = CherrypickOptions::new();
+        let mut checkout = CheckoutBuilder::new();
+        checkout.allow_conflicts(true).conflict_style_merge(true);
+        opts.checkout_builder(checkout);
+        repo.cherrypick(&repo.find_commit(oid)?, Some(&mut opts))?;
+
+        Ok(())
+    }
+
+    // keep `oid` under `refname`, with a reflog entry saying when
+    pub fn keep_ref(&self, refname: &str, oid: Oid, msg: &str) -> Result<()> {
+        let repo = &self.0;
+        repo.reference(refname, oid, true, msg)?;
+        let signature = self.signature_allow_undefined_name()?;
+        let mut reflog = repo.reflog(refname)?;
+        reflog.append(oid, &signature, Some(msg))?;
+        reflog.write()?;
+
+        Ok(())
+    }
+
+    // keep `oid` as the annotated tag `name`, replacing an older one
+    pub fn keep_tag(&self, name: &str, oid: Oid, msg: &str) -> Result<()> {
+        let repo = &self.0;
+        let signature = self.signature_allow_undefined_name()?;
+        repo.tag(name, &repo.find_object(oid, None)?, &signature, msg, true)?;
+
         Ok(())
     }
 
+    // when a ref written by keep_ref or keep_tag was written, the commit
+    // time for anything else
+    pub fn kept_since(&self, refname: &str) -> Result<i64> {
+        let repo = &self.0;
+        let reference = repo.find_reference(refname)?;
+
+        if let Ok(tag) = reference.peel_to_tag() {
+            if let Some(tagger) = tag.tagger() {
+                return Ok(tagger.when().seconds());
+            }
+        }
+        if let Some(entry) = repo.reflog(refname)?.get(0) {
+            return Ok(entry.committer().when().seconds());
+        }
+
+        Ok(reference.peel_to_commit()?.time().seconds())
+    }
+
+    // the remote tracking branch of a local branch, if any
+    pub fn upstream(&self, branch: &str) -> Option<(String, Oid)> {
+        let b = self.0.find_branch(branch, BranchType::Local).ok()?;
+        let upstream = b.upstream().ok()?;
+        let name = upstream.name().ok()??.to_string();
+        let oid = upstream.get().target()?;
+
+        Some((name, oid))
+    }
+
     pub fn delete_branch(&self, branch: &str) -> Result<()> {
-        self.0.find_branch(branch, BranchType::Local)?.delete()?;
+        self.0.find_branch(branch, BranchType::Local)
+            .map_err(|_| Error::BranchNotFound(branch.to_string()))?
+            .delete()?;
+        // git-flow AVH keeps the base of a branch outside of its section
+        let _ = self.0.config()?.remove(&format!("gitflow.branch.{}.base", branch));
 
         Ok(())
     }
@@ -340,43 +968,69 @@ impl GitcRepo {
     pub fn pull(&self, remote_name: &str, branch: &str) -> Result<()> {
         let repo = &self.0;
 
-        self.fetch(&remote_name, &[&branch])?;
+        self.fetch(remote_name, &[branch], None)?;
 
         let fetch_head = repo.find_reference("FETCH_HEAD")?;
         let fetch_commit = repo.reference_to_annotated_commit(&fetch_head)?;
 
-        self.merge(&branch, fetch_commit, &format!("Pull {} from {} and merge", branch, remote_name))?;
+        self.merge(branch, fetch_commit, &format!("Pull {} from {} and merge", branch, remote_name))?;
 
         Ok(())
     }
 
-    fn push(&self, remote_name: &str, branch: &str, user: &str, pass: &str) -> Result<()> {
+    pub fn push(&self, remote_name: &str, branch: &str) -> Result<()> {
         let repo = &self.0;
         let mut remote = repo.find_remote(remote_name)?;
-        let mut callbacks = RemoteCallbacks::new();
+        // the remote reports rejected refs here instead of failing the push
+        let mut rejected = None;
+        let mut callbacks = self.remote_callbacks()?;
         let mut opts = PushOptions::new();
 
-        // TODO too simple to get credentials
-        callbacks.credentials(|_url, _usernaem_from_url, _allowed_types| {
-            Cred::userpass_plaintext(user, pass)
+        callbacks.push_update_reference(|_refname, status| {
+            if let Some(reason) = status {
+                rejected = Some(reason.to_string());
+            }
+            Ok(())
         });
         opts.remote_callbacks(callbacks);
 
         let refname = format!("refs/heads/{}", branch);
-        remote.push(&[&refname], Some(&mut opts))?;
+        remote.push(&[&format!("{}:{}", refname, refname)], Some(&mut opts))?;
+        drop(opts);
+
+        if let Some(reason) = rejected {
+            return Err(Error::RemoteRejected {
+                remote: remote_name.to_string(),
+                refname,
+                reason,
+            });
+        }
+
+        // the remote has it now, so does our view of the remote
+        let oid = repo.refname_to_id(&refname)?;
+        repo.reference(
+            &format!("refs/remotes/{}/{}", remote_name, branch),
+            oid,
+            true,
+            &format!("push: update {}/{}", remote_name, branch),
+        )?;
 
         Ok(())
     }
 
-    fn rebase(&self, branch_from: &str, branch_to: &str) -> Result<()> {
+    // make `branch` track `remote`/`branch`
+    pub fn set_tracking(&self, branch: &str, remote_name: &str) -> Result<()> {
+        self.config(&format!("branch.{}.remote", branch), remote_name)?;
+        self.config(&format!("branch.{}.merge", branch), &format!("refs/heads/{}", branch))?;
+
+        Ok(())
+    }
+
+    // rebase `branch_from` onto `branch_to`, on conflicts the rebase is
+    // given up and nothing changes
+    pub fn rebase(&self, branch_from: &str, branch_to: &str) -> Result<()> {
         let repo = &self.0;
-        let head = if let Some(head) = repo.head()?.target() {
-            head
-        } else {
-            return Err(Error::NoHead);
-        };
-        let tid = repo.find_commit(head)?;
-        let signature = tid.author();
+        let signature = self.signature_allow_undefined_name()?;
 
         let mut opts = RebaseOptions::default();
         let refname_from = format!("refs/heads/{}", branch_from);
@@ -393,10 +1047,18 @@ impl GitcRepo {
             None,
             Some(&mut opts))?;
 
-        while let Some(_operation) = rebase.next() {
-            let _operation = _operation?;
-            println!("Operation: {:?}", _operation.kind());
-            rebase.commit(None, &signature, None)?;
+        while let Some(operation) = rebase.next() {
+            operation?;
+            if repo.index()?.has_conflicts() {
+                rebase.abort()?;
+                return Err(Error::Generic(format!(
+                    "rebasing {} onto {} caused conflicts, nothing was changed", branch_from, branch_to)));
+            }
+            match rebase.commit(None, &signature, None) {
+                // already on the new base
+                Err(e) if e.code() == ErrorCode::Applied => continue,
+                v => v?,
+            };
         }
 
         rebase.finish(None)?;
@@ -405,13 +1067,12 @@ impl GitcRepo {
     }
 }
 
+// the tests from before the library, kept as they were
 #[cfg(test)]
+#[allow(dead_code, unused_imports, unused_must_use, clippy::assertions_on_constants, clippy::needless_borrows_for_generic_args)]
 mod tests {
     use crate::{
-        utils::{
-            is_repo,
-            get_branch_name,
-        },
+        utils::get_branch_name,
         error::{
             Error,
             Result,
@@ -428,7 +1089,10 @@ mod tests {
     fn set_test_repo(path: &str) -> Result<GitcRepo> {
         let mut p = PathBuf::with_capacity(40);
         p.push(path);
-        let repo = GitcRepo::new(&p);
+        let repo = GitcRepo::create(&p)?;
+        // init commits, don't depend on the identity of whoever runs the tests
+        repo.config("user.name", "git-flow")?;
+        repo.config("user.email", "git-flow@localhost")?;
         Ok(repo)
     }
 
@@ -522,13 +1186,16 @@ mod tests {
 
     #[test]
     fn test_is_repo() {
-        let repo = set_test_repo("/tmp/abc");
+        // not the repository test_flow commits to
+        let _ = remove_dir_all("/tmp/abc-empty");
+        let repo = set_test_repo("/tmp/abc-empty");
         let repo = repo.unwrap().0;
         assert!(repo.is_empty().unwrap());
     }
 
     #[test]
     fn test_flow() {
+        let _ = remove_dir_all("/tmp/abc");
         test_init();
         test_branch();
         test_checkout();
@@ -0,0 +1,256 @@
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        BufRead,
+        BufReader,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+};
+
+use git2::{
+    ConfigLevel,
+    Repository,
+};
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
+use crate::{
+    utils::now,
+    error::{
+        Error,
+        Result,
+    },
+};
+
+/// A ref moved by an operation, `None` when it didn't exist on that side.
+///
+/// For `HEAD` the values are the branch it points to, e.g.
+/// `refs/heads/develop`, or the commit of a detached HEAD.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RefChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// One operation in the journal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: usize,
+    /// What was run, e.g. `feature finish x`.
+    pub command: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    pub refs: Vec<RefChange>,
+    pub tags: Vec<String>,
+    pub deleted: Vec<String>,
+    /// The settings of local branches it changed, e.g. the base start
+    /// remembers, named like in git config.
+    #[serde(default)]
+    pub config: Vec<RefChange>,
+    /// The entry an undo reverted.
+    pub undoes: Option<usize>,
+}
+
+impl Entry {
+    // an entry from the refs before and after an operation, with the
+    // created tags and deleted branches picked out of the changes
+    pub fn new(command: &str, before: &RefState, after: &RefState) -> Self {
+        let refs = changes(&before.0, &after.0);
+        let tags = refs.iter()
+            .filter(|r| r.before.is_none())
+            .filter_map(|r| r.name.strip_prefix("refs/tags/"))
+            .map(|t| t.to_string())
+            .collect();
+        let deleted = refs.iter()
+            .filter(|r| r.after.is_none())
+            .filter_map(|r| r.name.strip_prefix("refs/heads/"))
+            .map(|b| b.to_string())
+            .collect();
+
+        Self {
+            id: 0,
+            command: command.to_string(),
+            time: now(),
+            refs,
+            tags,
+            deleted,
+            config: changes(&before.1, &after.1),
+            undoes: None,
+        }
+    }
+}
+
+// what differs from `before` to `after`
+fn changes(before: &BTreeMap<String, String>, after: &BTreeMap<String, String>) -> Vec<RefChange> {
+    let mut changes = Vec::new();
+    let names = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    for name in names {
+        let (b, a) = (before.get(name), after.get(name));
+        if b != a {
+            changes.push(RefChange {
+                name: name.clone(),
+                before: b.cloned(),
+                after: a.cloned(),
+            });
+        }
+    }
+
+    changes
+}
+
+// the settings git and git-flow keep per branch, which come and go with it
+const BRANCH_CONFIG: &str = r"^(branch\..*|gitflow\.branch\..*\.base)$";
+
+/// HEAD, the local branches and the tags at one point in time, then the
+/// settings of the branches in the repository config.
+#[derive(Clone, Debug, Default)]
+pub struct RefState(pub BTreeMap<String, String>, pub BTreeMap<String, String>);
+
+impl RefState {
+    pub fn read(repo: &Repository) -> Result<Self> {
+        let mut refs = BTreeMap::new();
+
+        for r in repo.references()? {
+            let r = r?;
+            let name = match r.name() {
+                Some(name) if name.starts_with("refs/heads/") || name.starts_with("refs/tags/") => name,
+                _ => continue,
+            };
+            if let Some(oid) = r.target() {
+                refs.insert(name.to_string(), oid.to_string());
+            }
+        }
+        if let Ok(head) = repo.find_reference("HEAD") {
+            let value = match head.symbolic_target() {
+                Some(target) => Some(target.to_string()),
+                None => head.target().map(|oid| oid.to_string()),
+            };
+            if let Some(value) = value {
+                refs.insert("HEAD".to_string(), value);
+            }
+        }
+
+        let mut config = BTreeMap::new();
+        if let Ok(local) = repo.config().and_then(|c| c.open_level(ConfigLevel::Local)) {
+            for entry in &local.entries(Some(BRANCH_CONFIG))? {
+                let entry = entry?;
+                if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+                    config.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+
+        Ok(Self(refs, config))
+    }
+}
+
+/// The operations git-flow did, one json object per line in
+/// `gitflow/journal` of the common git directory.
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// The journal of the repository with common git directory `gitdir`.
+    pub fn new(gitdir: &Path) -> Self {
+        Self {
+            path: gitdir.join("gitflow").join("journal"),
+        }
+    }
+
+    /// All entries, oldest first.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(|e| Error::Generic(
+                        format!("{} is corrupted: {}", self.path.display(), e)))?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Append `entry` with the next id, which is returned.
+    pub fn append(&self, mut entry: Entry) -> Result<Entry> {
+        entry.id = self.entries()?.last().map_or(1, |e| e.id + 1);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let line = serde_json::to_string(&entry).map_err(|e| Error::Generic(e.to_string()))?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", line)?;
+
+        Ok(entry)
+    }
+
+    /// The latest entry that moved a local ref and is not undone yet,
+    /// undos themselves are skipped.
+    pub fn last_undoable(&self) -> Result<Option<Entry>> {
+        let entries = self.entries()?;
+        let undone = entries.iter().filter_map(|e| e.undoes).collect::<Vec<_>>();
+
+        Ok(entries.into_iter()
+            .rev()
+            .find(|e| e.undoes.is_none() && !e.refs.is_empty() && !undone.contains(&e.id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::remove_dir_all,
+    };
+    use super::{
+        Entry,
+        Journal,
+        RefState,
+    };
+
+    #[test]
+    fn test_journal() {
+        let dir = env::temp_dir().join("gf-journal");
+        let _ = remove_dir_all(&dir);
+        let journal = Journal::new(&dir);
+        assert!(journal.entries().unwrap().is_empty());
+
+        let mut before = RefState::default();
+        before.0.insert("refs/heads/feature/x".to_string(), "a".to_string());
+        let mut after = RefState::default();
+        after.0.insert("refs/tags/1.0".to_string(), "b".to_string());
+
+        let first = journal.append(Entry::new("release finish 1.0", &before, &after)).unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(first.tags, vec!["1.0"]);
+        assert_eq!(first.deleted, vec!["feature/x"]);
+
+        let mut undo = Entry::new("undo", &after, &before);
+        undo.undoes = Some(first.id);
+        assert_eq!(journal.append(undo).unwrap().id, 2);
+        assert_eq!(journal.entries().unwrap().len(), 2);
+        assert!(journal.last_undoable().unwrap().is_none());
+    }
+}
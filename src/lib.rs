@@ -1,5 +1,65 @@
-mod error;
+//! git-flow as a library.
+//!
+//! ```no_run
+//! use git_flow::{Flow, GfBranch, StartOptions, FinishOptions};
+//!
+//! let flow = Flow::open(".")?;
+//! let started = flow.start(&GfBranch::Feature, "x", &StartOptions::default())?;
+//! println!("created {} from {}", started.branch, started.base);
+//! let finished = flow.finish(&GfBranch::Feature, "x", &FinishOptions::default())?;
+//! println!("deleted {:?}", finished.deleted);
+//! # Ok::<(), git_flow::Error>(())
+//! ```
+pub mod error;
 mod utils;
 mod gitc;
 mod gf;
-mod cli;
+pub mod journal;
+pub mod flow;
+mod output;
+// the command line of the git-flow binary, not part of the library
+#[doc(hidden)]
+pub mod cli;
+
+pub use crate::{
+    error::{
+        Error,
+        Result,
+    },
+    gf::GfBranch,
+    gitc::{
+        GitcRepo,
+        MergeKind,
+        Worktree,
+    },
+    flow::{
+        Flow,
+        InitOptions,
+        StartOptions,
+        FinishOptions,
+        DeleteOptions,
+        StartOutcome,
+        FinishOutcome,
+        Merge,
+        BranchInfo,
+        FlowConfig,
+        FlowStatus,
+        SyncInfo,
+        RemoteOutcome,
+        BranchOutcome,
+        RenameOutcome,
+        Plan,
+        Step,
+        UndoOutcome,
+        ArchiveInfo,
+        PickKind,
+        BackportLine,
+        BackportOutcome,
+        RebaseOutcome,
+        DiffOutcome,
+    },
+    journal::{
+        Entry,
+        RefChange,
+    },
+};
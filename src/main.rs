@@ -1,23 +1,38 @@
-mod error;
-mod utils;
-mod gitc;
-mod gf;
-mod cli;
+use std::{
+    io::{
+        self,
+        ErrorKind,
+        Write,
+    },
+    process::exit,
+};
 
-use crate::cli::cli_run;
-use crate::error::Error;
+use git_flow::{
+    cli::cli_run,
+    Error,
+};
 
 fn main() {
     // the main is a handler to all final results
     match cli_run() {
-        Ok(s) => println!("{}", s),
+        Ok(s) => {
+            let mut out = io::stdout().lock();
+            match writeln!(out, "{}", s).and_then(|_| out.flush()) {
+                // the reader went away, e.g. `| head`, nothing left to say
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    exit(Error::from(e).exit_code());
+                }
+                Ok(()) => {}
+            }
+        }
         Err(e) => {
-            match e {
-                Error::Generic(generr) => eprintln!("generic: {}", generr),
-                Error::NoHead => eprintln!("Git2: no head"),
-                Error::Git(giterr) => eprintln!("Git2 {}", giterr),
-                Error::Io(ioerr) => eprintln!("Io {}", ioerr),
+            eprintln!("error: {}", e);
+            if let Some(hint) = e.hint() {
+                eprintln!("hint: {}", hint);
             }
+            exit(e.exit_code());
         }
     }
 }
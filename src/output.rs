@@ -0,0 +1,545 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    flow::{
+        StartOutcome,
+        FinishOutcome,
+        BranchInfo,
+        FlowConfig,
+        FlowStatus,
+        SyncInfo,
+        RemoteOutcome,
+        BranchOutcome,
+        RenameOutcome,
+        Plan,
+        Step,
+        UndoOutcome,
+        ArchiveInfo,
+        BackportOutcome,
+        RebaseOutcome,
+        DiffOutcome,
+    },
+    journal::{
+        Entry,
+        RefChange,
+    },
+    utils::format_time,
+    error::{
+        Error,
+        Result,
+    },
+};
+
+// How results are written to stdout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // for humans, may change between versions
+    Text,
+    // a single json object per command
+    Json,
+    // tab separated records, one per line
+    Porcelain,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "porcelain" => Ok(Format::Porcelain),
+            _ => Err(Error::Generic(format!("unknown output format {}", s))),
+        }
+    }
+}
+
+// The result of every command, tagged with the command in json
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum GfOutput {
+    Init {
+        path: String,
+    },
+    Start(StartOutcome),
+    Finish(FinishOutcome),
+    List {
+        #[serde(rename = "type")]
+        kind: String,
+        // the base of the type, branches may have their own
+        base: String,
+        branches: Vec<BranchInfo>,
+    },
+    Config(FlowConfig),
+    Status(FlowStatus),
+    Publish(RemoteOutcome),
+    Track(RemoteOutcome),
+    Checkout(BranchOutcome),
+    Delete(BranchOutcome),
+    Rename(RenameOutcome),
+    // what a command would do with --dry-run
+    DryRun(Plan),
+    Log {
+        entries: Vec<Entry>,
+    },
+    Undo(UndoOutcome),
+    Restore(BranchOutcome),
+    Archive {
+        archives: Vec<ArchiveInfo>,
+    },
+    Prune {
+        pruned: Vec<ArchiveInfo>,
+    },
+    Backport(BackportOutcome),
+    Rebase(RebaseOutcome),
+    Diff(DiffOutcome),
+}
+
+impl GfOutput {
+    pub fn render(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Json => serde_json::to_string(self)
+                .map_err(|e| Error::Generic(e.to_string())),
+            Format::Text => Ok(self.text()),
+            Format::Porcelain => Ok(self.porcelain()),
+        }
+    }
+
+    fn text(&self) -> String {
+        let lines = match self {
+            GfOutput::Init { path } => vec![format!("init {} success", path)],
+            GfOutput::Start(o) => match &o.worktree {
+                Some(path) => vec![format!("Started '{}' from '{}' in {}", o.branch, o.base, path)],
+                None => vec![format!("Started '{}' from '{}'", o.branch, o.base)],
+            },
+            GfOutput::Publish(o) => vec![format!("Published '{}' to '{}'", o.branch, o.remote)],
+            GfOutput::Track(o) => vec![format!("Tracking '{}' from '{}'", o.branch, o.remote)],
+            GfOutput::Checkout(o) => match &o.worktree {
+                Some(path) => vec![format!("'{}' is checked out in {}, cd there", o.branch, path)],
+                None => vec![format!("Switched to branch '{}'", o.branch)],
+            },
+            GfOutput::Delete(o) => {
+                let mut lines = vec![format!("Deleted branch '{}' (was {})", o.branch, o.head)];
+                if let Some(path) = &o.worktree {
+                    lines.push(format!("Removed the worktree {}", path));
+                }
+                lines
+            }
+            GfOutput::Rename(o) => vec![format!("Renamed '{}' to '{}'", o.from, o.to)],
+            GfOutput::Restore(o) => vec![format!("Restored branch '{}' at {}", o.branch, o.head)],
+            GfOutput::Rebase(o) => vec![format!("Rebased '{}' onto '{}'", o.branch, o.base)],
+            GfOutput::Diff(o) => {
+                if o.files.is_empty() {
+                    vec![format!("No changes in '{}' against '{}'.", o.branch, o.base)]
+                } else {
+                    vec![o.patch.trim_end().to_string()]
+                }
+            }
+            GfOutput::Archive { archives } => {
+                if archives.is_empty() {
+                    vec!["No archived branches.".to_string()]
+                } else {
+                    archives.iter()
+                        .map(|a| format!("{} {} archived {}",
+                                a.branch, &a.head.to_string()[..7], format_time(a.time)))
+                        .collect()
+                }
+            }
+            GfOutput::Prune { pruned } => {
+                if pruned.is_empty() {
+                    vec!["Nothing to prune.".to_string()]
+                } else {
+                    pruned.iter()
+                        .map(|a| format!("Pruned '{}' (was {})", a.refname, a.head))
+                        .collect()
+                }
+            }
+            GfOutput::Backport(o) => {
+                let mut lines = Vec::new();
+                for l in &o.lines {
+                    lines.push(match &l.conflict {
+                        Some(c) if o.stopped => format!("Conflict picking {} onto '{}'", short(c), l.branch),
+                        Some(c) => format!("Gave up picking {} onto '{}'", short(c), l.branch),
+                        None => format!("Backported onto '{}': {} picked, {} already applied",
+                                l.branch, l.picked.len(), l.applied.len()),
+                    });
+                }
+                for b in &o.pending {
+                    lines.push(format!("Not backported onto '{}'", b));
+                }
+                if o.stopped {
+                    lines.push("Resolve the conflicts, stage them and run `git flow backport --continue`, \
+                        or `git flow backport --abort`".to_string());
+                }
+                lines
+            }
+            GfOutput::DryRun(plan) => {
+                let mut lines = vec![format!("Dry run, {} would:", plan.operation)];
+                lines.extend(plan.steps.iter().map(|step| format!("  {}", step_text(step))));
+                lines
+            }
+            GfOutput::Log { entries } => {
+                if entries.is_empty() {
+                    vec!["The journal is empty.".to_string()]
+                } else {
+                    let mut lines = Vec::new();
+                    for e in entries {
+                        lines.push(format!("#{} {} {}", e.id, format_time(e.time), entry_title(e)));
+                        lines.extend(e.refs.iter().map(|r| format!("    {}", ref_text(r))));
+                    }
+                    lines
+                }
+            }
+            GfOutput::Undo(o) => {
+                let mut lines = vec![format!("Undid #{} {}", o.undone.id, o.undone.command)];
+                lines.extend(o.entry.refs.iter().map(|r| format!("    {}", ref_text(r))));
+                lines
+            }
+            GfOutput::Finish(o) => {
+                let mut lines = Vec::new();
+                for m in &o.merges {
+                    lines.push(format!("Merged '{}' into '{}' ({})",
+                            m.from, m.into, serde_kind(&m.kind).replace('_', "-")));
+                }
+                if let Some(tag) = &o.tag {
+                    lines.push(format!("Tagged '{}'", tag));
+                }
+                for b in &o.deleted {
+                    lines.push(format!("Deleted branch '{}'", b));
+                }
+                if let Some(path) = &o.worktree {
+                    lines.push(format!("Removed the worktree {}", path));
+                }
+                lines
+            }
+            GfOutput::List { kind, base, branches } => {
+                if branches.is_empty() {
+                    vec![format!("No {} branches exist.", kind)]
+                } else {
+                    branches.iter()
+                        .map(|b| {
+                            let mark = if b.current { "*" } else { " " };
+                            if b.base == *base {
+                                format!("{} {}", mark, b.name)
+                            } else {
+                                format!("{} {} (based on {})", mark, b.name, b.base)
+                            }
+                        })
+                        .collect()
+                }
+            }
+            GfOutput::Config(c) => {
+                let mut lines = vec![
+                    format!("Branch name for production releases: {}", c.master),
+                    format!("Branch name for \"next release\" development: {}", c.develop),
+                ];
+                for (kind, prefix) in &c.prefixes {
+                    lines.push(format!("{} branch prefix: {}", kind, prefix));
+                }
+                lines.push(format!("Version tag prefix: {}", c.versiontag));
+                lines.push(format!("Hooks and filters directory: {}", c.hooks));
+                lines.push(format!("Remote to publish to: {}", c.origin));
+                lines.push(format!("Remote to fetch bases from: {}", c.upstream));
+                if !c.unsupported.is_empty() {
+                    lines.push(format!("Unsupported settings, ignored: {}", c.unsupported.join(", ")));
+                }
+                lines
+            }
+            GfOutput::Status(st) => {
+                let mut lines = vec![match (&st.current, &st.current_type) {
+                    (Some(c), Some(t)) => format!("On branch {} ({})", c, t),
+                    (Some(c), None) => format!("On branch {}", c),
+                    _ => "HEAD detached".to_string(),
+                }];
+                lines.push(sync_text(&st.master));
+                lines.push(sync_text(&st.develop));
+                for (kind, branches) in &st.branches {
+                    if branches.is_empty() {
+                        continue;
+                    }
+                    lines.push(format!("Open {} branches:", kind));
+                    for b in branches {
+                        lines.push(format!("{} {} (ahead {}, behind {} of {})",
+                                if b.current { "*" } else { " " },
+                                b.branch, b.ahead, b.behind, b.base));
+                    }
+                }
+                if let Some(op) = &st.in_progress {
+                    lines.push(format!("In progress: {}", op));
+                }
+                for w in &st.warnings {
+                    lines.push(format!("warning: {}", w));
+                }
+                lines
+            }
+        };
+
+        lines.join("\n")
+    }
+
+    fn porcelain(&self) -> String {
+        let lines = match self {
+            GfOutput::Init { path } => vec![format!("init\t{}", path)],
+            GfOutput::Start(o) => {
+                let mut lines = vec![format!("start\t{}\t{}\t{}", o.branch, o.base, o.head)];
+                lines.extend(o.worktree.iter().map(|p| format!("worktree\t{}", p)));
+                lines
+            }
+            GfOutput::Publish(o) => vec![format!("publish\t{}\t{}\t{}", o.branch, o.remote, o.head)],
+            GfOutput::Track(o) => vec![format!("track\t{}\t{}\t{}", o.branch, o.remote, o.head)],
+            GfOutput::Checkout(o) => {
+                let mut lines = vec![format!("checkout\t{}\t{}", o.branch, o.head)];
+                lines.extend(o.worktree.iter().map(|p| format!("worktree\t{}", p)));
+                lines
+            }
+            GfOutput::Delete(o) => {
+                let mut lines = vec![format!("delete\t{}\t{}", o.branch, o.head)];
+                lines.extend(o.worktree.iter().map(|p| format!("remove_worktree\t{}", p)));
+                lines
+            }
+            GfOutput::Rename(o) => vec![format!("rename\t{}\t{}\t{}", o.from, o.to, o.head)],
+            GfOutput::Restore(o) => vec![format!("restore\t{}\t{}", o.branch, o.head)],
+            GfOutput::Rebase(o) => vec![format!("rebase\t{}\t{}\t{}", o.branch, o.base, o.head)],
+            GfOutput::Diff(o) => o.files.iter().map(|f| format!("file\t{}", f)).collect(),
+            GfOutput::Archive { archives } => {
+                archives.iter()
+                    .map(|a| format!("archive\t{}\t{}\t{}\t{}", a.branch, a.refname, a.head, a.time))
+                    .collect()
+            }
+            GfOutput::Prune { pruned } => {
+                pruned.iter()
+                    .map(|a| format!("prune\t{}\t{}\t{}", a.branch, a.refname, a.head))
+                    .collect()
+            }
+            GfOutput::DryRun(plan) => plan.steps.iter().map(step_porcelain).collect(),
+            GfOutput::Backport(o) => {
+                let mut lines = Vec::new();
+                for l in &o.lines {
+                    lines.push(format!("line\t{}\t{}\t{}\t{}",
+                            l.branch, l.picked.len(), l.applied.len(), l.conflict.as_deref().unwrap_or("")));
+                }
+                lines.extend(o.pending.iter().map(|b| format!("pending\t{}", b)));
+                if o.stopped {
+                    lines.push("stopped".to_string());
+                }
+                lines
+            }
+            GfOutput::Log { entries } => {
+                let mut lines = Vec::new();
+                for e in entries {
+                    lines.push(format!("entry\t{}\t{}\t{}\t{}",
+                            e.id, e.time, e.command, e.undoes.map(|u| u.to_string()).unwrap_or_default()));
+                    lines.extend(e.refs.iter().map(|r| format!("ref\t{}\t{}", e.id, ref_porcelain(r))));
+                }
+                lines
+            }
+            GfOutput::Undo(o) => {
+                let mut lines = vec![format!("undo\t{}\t{}", o.undone.id, o.entry.id)];
+                lines.extend(o.entry.refs.iter().map(|r| format!("ref\t{}\t{}", o.entry.id, ref_porcelain(r))));
+                lines
+            }
+            GfOutput::Finish(o) => {
+                let mut lines = Vec::new();
+                for m in &o.merges {
+                    lines.push(format!("merge\t{}\t{}\t{}\t{}",
+                            m.from, m.into, serde_kind(&m.kind), m.head));
+                }
+                if let Some(tag) = &o.tag {
+                    lines.push(format!("tag\t{}", tag));
+                }
+                for b in &o.deleted {
+                    lines.push(format!("delete\t{}", b));
+                }
+                lines.extend(o.worktree.iter().map(|p| format!("remove_worktree\t{}", p)));
+                lines
+            }
+            GfOutput::List { branches, .. } => {
+                branches.iter()
+                    .map(|b| format!("{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                            if b.current { "*" } else { " " },
+                            b.branch, b.name, b.base, b.head, b.ahead, b.behind))
+                    .collect()
+            }
+            GfOutput::Config(c) => {
+                let mut lines = vec![
+                    format!("gitflow.branch.master\t{}", c.master),
+                    format!("gitflow.branch.develop\t{}", c.develop),
+                ];
+                for (kind, prefix) in &c.prefixes {
+                    lines.push(format!("gitflow.prefix.{}\t{}", kind, prefix));
+                }
+                lines.push(format!("gitflow.prefix.versiontag\t{}", c.versiontag));
+                lines.push(format!("gitflow.path.hooks\t{}", c.hooks));
+                lines.push(format!("gitflow.origin\t{}", c.origin));
+                lines.push(format!("gitflow.upstream\t{}", c.upstream));
+                lines.extend(c.unsupported.iter().map(|k| format!("unsupported\t{}", k)));
+                lines
+            }
+            GfOutput::Status(st) => {
+                let mut lines = vec![format!("current\t{}\t{}",
+                        st.current.as_deref().unwrap_or(""),
+                        st.current_type.as_deref().unwrap_or(""))];
+                for sync in &[&st.master, &st.develop] {
+                    lines.push(format!("base\t{}\t{}\t{}\t{}\t{}",
+                            sync.branch, sync.head, sync.upstream.as_deref().unwrap_or(""),
+                            sync.ahead, sync.behind));
+                }
+                for (kind, branches) in &st.branches {
+                    for b in branches {
+                        lines.push(format!("branch\t{}\t{}\t{}\t{}\t{}\t{}",
+                                kind, b.branch, b.base, b.head, b.ahead, b.behind));
+                    }
+                }
+                if let Some(op) = &st.in_progress {
+                    lines.push(format!("in_progress\t{}", op));
+                }
+                for w in &st.warnings {
+                    lines.push(format!("warning\t{}", w));
+                }
+                lines
+            }
+        };
+
+        lines.join("\n")
+    }
+}
+
+fn sync_text(sync: &SyncInfo) -> String {
+    match &sync.upstream {
+        Some(upstream) if sync.ahead == 0 && sync.behind == 0 => {
+            format!("{}: up to date with {}", sync.branch, upstream)
+        }
+        Some(upstream) => {
+            format!("{}: ahead {}, behind {} of {}", sync.branch, sync.ahead, sync.behind, upstream)
+        }
+        None => format!("{}: no remote tracking branch", sync.branch),
+    }
+}
+
+fn step_text(step: &Step) -> String {
+    match step {
+        Step::Config { key, value } => format!("set {} to '{}'", key, value),
+        Step::Fetch { remote, branch } => format!("fetch '{}' from '{}'", branch, remote),
+        Step::Create { branch, base } => format!("create '{}' from '{}'", branch, base),
+        Step::Checkout { branch } => format!("switch to '{}'", branch),
+        Step::Merge { from, into, kind } => format!("merge '{}' into '{}' ({})",
+                from, into, serde_kind(kind).replace('_', "-")),
+        Step::Tag { name, branch } => format!("tag '{}' as '{}'", branch, name),
+        Step::Push { remote, branch } => format!("push '{}' to '{}'", branch, remote),
+        Step::SetUpstream { branch, remote } => format!("make '{}' track '{}/{}'", branch, remote, branch),
+        Step::Rename { from, to } => format!("rename '{}' to '{}'", from, to),
+        Step::Delete { branch } => format!("delete '{}'", branch),
+        Step::Rebase { branch, onto } => format!("rebase '{}' onto '{}'", branch, onto),
+        Step::Restore { name, to: Some(to) } => format!("reset '{}' to {}", name, to),
+        Step::Restore { name, to: None } => format!("remove '{}'", name),
+        Step::Pick { commit, onto, kind } => format!("pick {} onto '{}' ({})",
+                short(commit), onto, serde_kind(kind).replace('_', "-")),
+        Step::AddWorktree { branch, path } => format!("check '{}' out in a new worktree at {}", branch, path),
+        Step::RemoveWorktree { path } => format!("remove the worktree {}", path),
+    }
+}
+
+fn step_porcelain(step: &Step) -> String {
+    match step {
+        Step::Config { key, value } => format!("config\t{}\t{}", key, value),
+        Step::Fetch { remote, branch } => format!("fetch\t{}\t{}", remote, branch),
+        Step::Create { branch, base } => format!("create\t{}\t{}", branch, base),
+        Step::Checkout { branch } => format!("checkout\t{}", branch),
+        Step::Merge { from, into, kind } => format!("merge\t{}\t{}\t{}", from, into, serde_kind(kind)),
+        Step::Tag { name, branch } => format!("tag\t{}\t{}", name, branch),
+        Step::Push { remote, branch } => format!("push\t{}\t{}", remote, branch),
+        Step::SetUpstream { branch, remote } => format!("set_upstream\t{}\t{}", branch, remote),
+        Step::Rename { from, to } => format!("rename\t{}\t{}", from, to),
+        Step::Delete { branch } => format!("delete\t{}", branch),
+        Step::Rebase { branch, onto } => format!("rebase\t{}\t{}", branch, onto),
+        Step::Restore { name, to } => format!("restore\t{}\t{}", name, to.as_deref().unwrap_or("")),
+        Step::Pick { commit, onto, kind } => format!("pick\t{}\t{}\t{}", commit, onto, serde_kind(kind)),
+        Step::AddWorktree { branch, path } => format!("add_worktree\t{}\t{}", branch, path),
+        Step::RemoveWorktree { path } => format!("remove_worktree\t{}", path),
+    }
+}
+
+fn entry_title(e: &Entry) -> String {
+    match e.undoes {
+        Some(id) => format!("undo of #{}", id),
+        None => e.command.clone(),
+    }
+}
+
+// a hex sha shortened like git does
+fn short(sha: &str) -> String {
+    sha.chars().take(7).collect()
+}
+
+// oids shortened, branch names of HEAD as is
+fn ref_text(r: &RefChange) -> String {
+    let value = |v: &Option<String>| match v {
+        Some(v) if v.starts_with("refs/") => v.clone(),
+        Some(v) => short(v),
+        None => "-".to_string(),
+    };
+
+    format!("{}: {} -> {}", r.name, value(&r.before), value(&r.after))
+}
+
+fn ref_porcelain(r: &RefChange) -> String {
+    format!("{}\t{}\t{}", r.name,
+            r.before.as_deref().unwrap_or(""), r.after.as_deref().unwrap_or(""))
+}
+
+// the same spelling json uses
+fn serde_kind<T: Serialize>(v: &T) -> String {
+    serde_json::to_value(v)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Oid;
+    use crate::{
+        flow::{
+            FinishOutcome,
+            Merge,
+        },
+        gitc::MergeKind,
+    };
+    use super::{
+        Format,
+        GfOutput,
+    };
+
+    #[test]
+    fn test_render_finish() {
+        let head = Oid::from_str("0123456789012345678901234567890123456789").unwrap();
+        let out = GfOutput::Finish(FinishOutcome {
+            branch: "feature/x".to_string(),
+            merges: vec![Merge {
+                from: "feature/x".to_string(),
+                into: "develop".to_string(),
+                kind: MergeKind::FastForward,
+                head,
+            }],
+            tag: None,
+            deleted: vec!["feature/x".to_string()],
+            worktree: None,
+        });
+
+        assert_eq!(
+            out.render(Format::Json).unwrap(),
+            "{\"command\":\"finish\",\"branch\":\"feature/x\",\"merges\":[{\"from\":\"feature/x\",\
+             \"into\":\"develop\",\"kind\":\"fast_forward\",\"head\":\"0123456789012345678901234567890123456789\"}],\
+             \"tag\":null,\"deleted\":[\"feature/x\"]}"
+        );
+        assert_eq!(
+            out.render(Format::Porcelain).unwrap(),
+            "merge\tfeature/x\tdevelop\tfast_forward\t0123456789012345678901234567890123456789\n\
+             delete\tfeature/x"
+        );
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
@@ -1,12 +1,13 @@
 use git2::{
     Repository,
-    RepositoryOpenFlags,
     Oid,
 };
 use std::{
-    io::{stdin, stdout, Write},
+    io::{stdin, stderr, Write},
     string::String,
     path::Path,
+    fs,
+    process::Command,
 };
 use crate::{
     error:: {
@@ -15,15 +16,6 @@ use crate::{
     }
 };
 
-pub fn is_repo(repo_path: &str) -> bool {
-    Repository::open_ext(
-        repo_path,
-        RepositoryOpenFlags::empty(),
-        Vec::<&Path>::new(),
-    )
-    .is_ok()
-}
-
 pub fn get_branch_name(repo: &Repository) -> Result<String> {
     let branch_iter = repo.branches(None)?;
 
@@ -48,14 +40,121 @@ pub fn get_head(repo: &Repository) -> Result<Oid> {
     }
 }
 
+// let the user edit `initial` in `editor` through the file at `path`, like
+// git does for commit messages. lines starting with `#` are dropped
+pub fn edit_message(editor: &str, path: &Path, initial: &str) -> Result<String> {
+    fs::write(path, format!("{}\n\n\
+        # Please enter the merge message. Lines starting with '#' will\n\
+        # be ignored, and an empty message aborts the finish.\n", initial))?;
+
+    // the editor may come with arguments, let the shell split them
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Generic(format!("there was a problem with the editor {}", editor)));
+    }
+
+    let msg = fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let _ = fs::remove_file(path);
+    let msg = msg.trim();
+    if msg.is_empty() {
+        return Err(Error::Generic("aborting due to empty merge message".to_string()));
+    }
+
+    Ok(msg.to_string())
+}
+
+// seconds since the unix epoch
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// an age like `30d`, `2w` or `12h` in seconds, plain numbers are days
+pub fn parse_age(age: &str) -> Result<i64> {
+    let (n, unit) = match age.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => age.split_at(i),
+        None => (age, "d"),
+    };
+    let secs = match unit {
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(Error::Generic(format!("unknown unit in {}, use h, d or w", age))),
+    };
+    let n = n.parse::<i64>().map_err(|_| Error::Generic(format!("{} is not an age like 30d", age)))?;
+
+    Ok(n * secs)
+}
+
+// `secs` since the unix epoch as `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_time(secs: i64) -> String {
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+// why `name` can't be a branch or tag name, following `git check-ref-format`
+pub fn check_ref_format(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    if name.starts_with('-') {
+        return Err("it starts with `-`".to_string());
+    }
+    if name == "@" {
+        return Err("`@` alone is not allowed".to_string());
+    }
+    if let Some(c) = name.chars().find(|c| c.is_ascii_control() || " ~^:?*[\\".contains(*c)) {
+        return Err(format!("it contains {:?}", c));
+    }
+    for bad in &["..", "@{", "//"] {
+        if name.contains(bad) {
+            return Err(format!("it contains `{}`", bad));
+        }
+    }
+    if name.ends_with('/') || name.ends_with('.') {
+        return Err("it ends with `/` or `.`".to_string());
+    }
+    for component in name.split('/') {
+        if component.starts_with('.') {
+            return Err(format!("the component `{}` starts with `.`", component));
+        }
+        if component.ends_with(".lock") {
+            return Err(format!("the component `{}` ends with `.lock`", component));
+        }
+    }
+
+    Ok(())
+}
+
 pub fn get_user_input(prompt: &str) -> Result<String> {
-    print!("{}: ", prompt);
-    stdout().flush()?;
+    // prompts go to stderr, stdout only carries results
+    eprint!("{}: ", prompt);
+    stderr().flush()?;
     let mut input = String::new();
-    match stdin().read_line(&mut input) {
-        Ok(_) => {},
-        Err(_) => {},
-    }
+    let _ = stdin().read_line(&mut input);
 
     Ok(input.trim().to_string())
 }